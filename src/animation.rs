@@ -0,0 +1,666 @@
+//! Animated GIF, APNG and SVG output of a run.
+
+use crate::observer;
+use crate::{FrameBuffer, Observer, Optimizer, Shape, ShapeKind, write_svg_header};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Frames sampled from a sequence of unknown length. When `record` adds a
+// frame to a full sequence every other frame is dropped, and from then on
+// only every other frame offered is kept, so a long run still fits and is
+// covered from start to end. The frames kept are evenly spaced as long as
+// the sequence holds an even number, so `record` rounds `max` down to one.
+struct Frames<T> {
+    items: Vec<T>,
+    // frames offered to `record` and how many of them make one kept frame
+    offered: u64,
+    stride: u64,
+}
+
+impl<T> Frames<T> {
+    fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            offered: 0,
+            stride: 1,
+        }
+    }
+
+    // offer the frame made by `frame`, keeping at most `max`; whether it was kept
+    fn record(&mut self, max: usize, frame: impl FnOnce() -> T) -> bool {
+        let max = max.max(2) / 2 * 2;
+        let keep = self.offered.is_multiple_of(self.stride);
+        if keep {
+            if self.items.len() >= max {
+                let mut i = 0;
+                self.items.retain(|_| {
+                    i += 1;
+                    i % 2 == 1
+                });
+                self.stride *= 2;
+            }
+            self.items.push(frame());
+        }
+        self.offered += 1;
+        keep
+    }
+
+    // append `frame`, replacing the last one if there are `max` already
+    fn push(&mut self, max: usize, frame: T) {
+        if self.items.len() >= max.max(1) {
+            self.items.pop();
+        }
+        self.items.push(frame);
+    }
+}
+
+/// A sequence of equally sized frames held in memory, of which at most
+/// `max_frames` are kept, see `record`.
+pub struct Animation {
+    pub width: u16,
+    pub height: u16,
+    /// Frames per second
+    pub fps: u32,
+    pub max_frames: usize,
+    /// Palette quantisation of GIFs, from 1 (best colours, slowest) to 30
+    pub gif_speed: i32,
+    // RGB, 3 bytes per pixel
+    frames: Frames<Vec<u8>>,
+}
+
+impl Animation {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            fps: 10,
+            max_frames: 200,
+            gif_speed: 10,
+            frames: Frames::new(),
+        }
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    pub fn gif_speed(mut self, gif_speed: i32) -> Self {
+        self.gif_speed = gif_speed;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.items.is_empty()
+    }
+
+    /// Append the contents of `fb`, which must have the animation's size. A
+    /// full animation has its last frame replaced instead.
+    pub fn push(&mut self, fb: &FrameBuffer) {
+        debug_assert_eq!((fb.width, fb.height), (self.width, self.height));
+        self.frames.push(self.max_frames, fb.pixels.clone());
+    }
+
+    /// Offer a frame of a sequence that may be longer than `max_frames`.
+    /// When a frame is added to a full animation every other frame is
+    /// dropped, and from then on only every other frame offered is kept, so
+    /// a long run still fits and is covered from start to end. An odd
+    /// `max_frames` is rounded down, which keeps the frames evenly spaced.
+    /// Returns whether the frame was kept.
+    pub fn record(&mut self, fb: &FrameBuffer) -> bool {
+        debug_assert_eq!((fb.width, fb.height), (self.width, self.height));
+        self.frames.record(self.max_frames, || fb.pixels.clone())
+    }
+
+    /// Save as APNG if `path` ends in `.png` or `.apng`, as GIF otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let apng = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("png") || e.eq_ignore_ascii_case("apng"));
+        let mut w = BufWriter::new(File::create(path)?);
+        if apng {
+            self.write_apng(&mut w)?;
+        } else {
+            self.write_gif(&mut w)?;
+        }
+        w.flush()
+    }
+
+    pub fn write_gif<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = GifEncoder::new_with_speed(w, self.gif_speed.clamp(1, 30));
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        let delay = Delay::from_numer_denom_ms(1000, self.fps.max(1));
+        let frames = self.frames.items.iter().map(|pixels| {
+            let rgba = pixels
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect();
+            let buffer = RgbaImage::from_raw(self.width as u32, self.height as u32, rgba)
+                .expect("frame has the animation's size");
+            Frame::from_parts(buffer, 0, 0, delay)
+        });
+        encoder.encode_frames(frames).map_err(io::Error::other)
+    }
+
+    pub fn write_apng<W: Write>(&self, w: W) -> io::Result<()> {
+        let frame_count = u32::try_from(self.len().max(1)).unwrap_or(u32::MAX);
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frame_count, 0)
+            .map_err(io::Error::other)?;
+        let fps = u16::try_from(self.fps.max(1)).unwrap_or(u16::MAX);
+        encoder.set_frame_delay(1, fps).map_err(io::Error::other)?;
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        for pixels in &self.frames.items {
+            writer.write_image_data(pixels).map_err(io::Error::other)?;
+        }
+        if self.is_empty() {
+            let blank = vec![0; self.width as usize * self.height as usize * 3];
+            writer.write_image_data(&blank).map_err(io::Error::other)?;
+        }
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+/// Records the best solution every `interval` generations, and once more at
+/// the end, and saves the animation to `path` when the run finishes
+pub struct AnimationRecorder {
+    animation: Animation,
+    path: String,
+    interval: u64,
+    fb: FrameBuffer,
+    // generation of the last frame kept
+    last: Option<u64>,
+}
+
+impl AnimationRecorder {
+    /// Frames are rendered with the size and settings of `fb`
+    pub fn new(animation: Animation, path: &str, interval: u64, fb: FrameBuffer) -> Self {
+        Self {
+            animation,
+            path: path.to_string(),
+            interval,
+            fb,
+            last: None,
+        }
+    }
+}
+
+impl<S: Shape> Observer<S> for AnimationRecorder {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let generation = observer::last_generation(optimizer);
+        if self.interval > 0 && generation.is_multiple_of(self.interval) {
+            self.fb.draw_shapes(optimizer.best());
+            if self.animation.record(&self.fb) {
+                self.last = Some(optimizer.generation());
+            }
+        }
+        Ok(())
+    }
+
+    fn on_finish(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        // the final frame is always kept, even when thinning
+        if self.last != Some(optimizer.generation()) {
+            self.fb.draw_shapes(optimizer.best());
+            self.animation.push(&self.fb);
+        }
+        self.animation.save(&self.path)
+    }
+}
+
+/// The best solutions of a run as one SVG, in which the shapes move and
+/// change colour from one snapshot to the next using SMIL animation. At
+/// most `max_frames` snapshots are kept, as for `Animation`.
+///
+/// Shapes are matched between snapshots by their position and kind; where a
+/// shape is missing it is fully transparent. Outside the animation the file
+/// shows the last snapshot, so `load_svg` reads back the final solution.
+pub struct SvgAnimation<S: Shape> {
+    pub width: u16,
+    pub height: u16,
+    /// Snapshots per second
+    pub fps: u32,
+    pub max_frames: usize,
+    frames: Frames<Vec<S>>,
+}
+
+impl<S: Shape> SvgAnimation<S> {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            fps: 10,
+            max_frames: 200,
+            frames: Frames::new(),
+        }
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.items.is_empty()
+    }
+
+    /// Append a snapshot of `shapes`, replacing the last one if full
+    pub fn push(&mut self, shapes: &[S]) {
+        self.frames.push(self.max_frames, shapes.to_vec());
+    }
+
+    /// Offer a snapshot of `shapes`, kept or not as by `Animation::record`.
+    /// Returns whether it was.
+    pub fn record(&mut self, shapes: &[S]) -> bool {
+        self.frames.record(self.max_frames, || shapes.to_vec())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_svg(&mut w)?;
+        w.flush()
+    }
+
+    pub fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_svg_header(w, self.width, self.height)?;
+        let frames = &self.frames.items;
+        let dur = frames.len() as f64 / self.fps.max(1) as f64;
+        let slots = frames.iter().map(Vec::len).max().unwrap_or(0);
+        for slot in 0..slots {
+            // a mixed shape may change kind, making another element
+            let mut kinds = Vec::new();
+            for shape in frames.iter().filter_map(|f| f.get(slot)) {
+                if !kinds.contains(&shape.kind()) {
+                    kinds.push(shape.kind());
+                }
+            }
+            for kind in kinds {
+                let track: Vec<Option<&S>> = frames
+                    .iter()
+                    .map(|f| f.get(slot).filter(|s| s.kind() == kind))
+                    .collect();
+                write_track(w, &track, dur)?;
+            }
+        }
+        writeln!(w, "</svg>")
+    }
+}
+
+// One element following a shape through the snapshots, transparent where
+// it is missing. Its static attributes are those of its last appearance.
+fn write_track<W: Write, S: Shape>(w: &mut W, track: &[Option<&S>], dur: f64) -> io::Result<()> {
+    // missing shapes keep the geometry of the closest earlier snapshot
+    let Some(mut shown) = track.iter().find_map(|s| *s) else {
+        return Ok(());
+    };
+    let mut filled = Vec::with_capacity(track.len());
+    for s in track {
+        shown = s.unwrap_or(shown);
+        filled.push(shown);
+    }
+
+    let mut element = Vec::new();
+    shown.write_svg(&mut element)?;
+    let element = String::from_utf8(element).map_err(io::Error::other)?;
+    let mut element = element
+        .trim_end()
+        .strip_suffix("/>")
+        .ok_or_else(|| io::Error::other("shape is not an empty SVG element"))?
+        .to_string();
+    if track.last().is_some_and(Option::is_none) {
+        let colour = shown.colour();
+        element = element.replace(&colour.svg_style(), &colour.svg_style_with_opacity(0.0));
+    }
+
+    let timing = format!(r#"dur="{dur}s" repeatCount="indefinite""#);
+    let mut children = String::new();
+    let mut animate = |name: &str, values: Vec<String>| {
+        if values.iter().all(|v| *v == values[0]) {
+            return;
+        }
+        // inside a run of equal values only its ends are needed
+        let n = values.len();
+        let kept: Vec<usize> = (0..n)
+            .filter(|&i| {
+                i == 0 || i == n - 1 || values[i - 1] != values[i] || values[i] != values[i + 1]
+            })
+            .collect();
+        let mut keys = String::new();
+        if kept.len() < n {
+            let times: Vec<String> = kept
+                .iter()
+                .map(|&i| ((i * 10000 / (n - 1)) as f64 / 10000.0).to_string())
+                .collect();
+            keys = format!(r#" keyTimes="{}""#, times.join(";"));
+        }
+        let values: Vec<&str> = kept.iter().map(|&i| values[i].as_str()).collect();
+        let values = values.join(";");
+        children.push_str(&if name == "transform" {
+            format!(
+                r#"<animateTransform attributeName="transform" type="rotate" values="{values}"{keys} {timing}/>"#
+            )
+        } else {
+            format!(r#"<animate attributeName="{name}" values="{values}"{keys} {timing}/>"#)
+        });
+    };
+
+    let attributes: Vec<_> = filled
+        .iter()
+        .map(|s| svg_attributes(s.kind(), &s.geometry()))
+        .collect();
+    for (i, &(name, _)) in attributes[0].iter().enumerate() {
+        animate(name, attributes.iter().map(|a| a[i].1.clone()).collect());
+    }
+    let fill = |s: &&S| {
+        let [r, g, b, _] = s.colour().rgba();
+        format!("#{r:02x}{g:02x}{b:02x}")
+    };
+    animate("fill", filled.iter().map(fill).collect());
+    let opacity = |s: &Option<&S>| s.map_or(0.0, |s| s.colour().opacity());
+    animate(
+        "fill-opacity",
+        track.iter().map(|s| format!("{:.2}", opacity(s))).collect(),
+    );
+
+    if children.is_empty() {
+        writeln!(w, "{element}/>")
+    } else {
+        let name = &element[1..element.find(' ').unwrap_or(element.len())];
+        writeln!(w, "{element}>{children}</{name}>")
+    }
+}
+
+// The animated attributes of a shape as written by `Shape::write_svg`, to a
+// tenth of a pixel; "transform" is the arguments of its rotation
+fn svg_attributes(kind: ShapeKind, geometry: &[f32]) -> Vec<(&'static str, String)> {
+    let geometry: Vec<f32> = geometry.iter().map(|v| (v * 10.0).round() / 10.0).collect();
+    match (kind, geometry.as_slice()) {
+        (ShapeKind::Triangle, &[x1, y1, x2, y2, x3, y3]) => {
+            vec![("points", format!("{x1},{y1} {x2},{y2} {x3},{y3}"))]
+        }
+        (ShapeKind::Circle, &[x, y, r]) => {
+            vec![
+                ("cx", x.to_string()),
+                ("cy", y.to_string()),
+                ("r", r.to_string()),
+            ]
+        }
+        (ShapeKind::Ellipse, &[x, y, rx, ry]) => vec![
+            ("cx", x.to_string()),
+            ("cy", y.to_string()),
+            ("rx", rx.to_string()),
+            ("ry", ry.to_string()),
+        ],
+        (ShapeKind::Rectangle, &[x1, y1, x2, y2]) => vec![
+            ("x", x1.to_string()),
+            ("y", y1.to_string()),
+            ("width", (x2 - x1 + 1.0).to_string()),
+            ("height", (y2 - y1 + 1.0).to_string()),
+        ],
+        (ShapeKind::RotatedRectangle, &[x, y, hw, hh, angle]) => vec![
+            ("x", (x - hw).to_string()),
+            ("y", (y - hh).to_string()),
+            ("width", (2.0 * hw).to_string()),
+            ("height", (2.0 * hh).to_string()),
+            ("transform", format!("{angle} {x} {y}")),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Records the best solution every `interval` generations, and once more at
+/// the end, as an `SvgAnimation` saved to `path` when the run finishes
+pub struct SvgAnimationRecorder<S: Shape> {
+    animation: SvgAnimation<S>,
+    path: String,
+    interval: u64,
+    // generation of the last snapshot kept
+    last: Option<u64>,
+}
+
+impl<S: Shape> SvgAnimationRecorder<S> {
+    pub fn new(animation: SvgAnimation<S>, path: &str, interval: u64) -> Self {
+        Self {
+            animation,
+            path: path.to_string(),
+            interval,
+            last: None,
+        }
+    }
+}
+
+impl<S: Shape> Observer<S> for SvgAnimationRecorder<S> {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let generation = observer::last_generation(optimizer);
+        if self.interval > 0
+            && generation.is_multiple_of(self.interval)
+            && self.animation.record(optimizer.best())
+        {
+            self.last = Some(optimizer.generation());
+        }
+        Ok(())
+    }
+
+    fn on_finish(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        // the final snapshot is always kept, even when thinning
+        if self.last != Some(optimizer.generation()) {
+            self.animation.push(optimizer.best());
+        }
+        self.animation.save(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::parse_svg;
+    use crate::{Circle, Colour, Genome};
+    use image::AnimationDecoder;
+    use image::codecs::gif::GifDecoder;
+    use std::io::Cursor;
+
+    #[test]
+    fn recorded_frames_are_evenly_spaced() {
+        for max in [2, 3, 4, 5, 8, 10] {
+            let mut frames = Frames::new();
+            for offered in 0..200 {
+                frames.record(max, || offered);
+                let items = &frames.items;
+                assert!(items.len() <= max, "max {max}: {items:?}");
+                assert!(
+                    items.len() >= max.min(offered + 1) / 2,
+                    "max {max}: {items:?}"
+                );
+                assert_eq!(items[0], 0);
+                let gap = items.get(1).map_or(1, |second| second - items[0]);
+                assert!(
+                    items.windows(2).all(|w| w[1] - w[0] == gap),
+                    "max {max}: {items:?}"
+                );
+                // the end of the run is covered
+                assert!(
+                    offered - items[items.len() - 1] < gap,
+                    "max {max}: {items:?}"
+                );
+            }
+        }
+    }
+
+    fn animation(frames: usize) -> Animation {
+        let mut animation = Animation::new(6, 4).max_frames(4);
+        let mut fb = FrameBuffer::new(6, 4);
+        for i in 0..frames {
+            fb.pixels.fill(i as u8 * 20);
+            animation.record(&fb);
+        }
+        animation
+    }
+
+    #[test]
+    fn gif_round_trips() {
+        for (offered, kept) in [(3, 3), (9, 3)] {
+            let animation = animation(offered);
+            assert_eq!(animation.len(), kept);
+            let mut gif = Vec::new();
+            animation.write_gif(&mut gif).unwrap();
+            let frames = GifDecoder::new(Cursor::new(gif))
+                .unwrap()
+                .into_frames()
+                .collect_frames()
+                .unwrap();
+            assert_eq!(frames.len(), kept);
+            for frame in &frames {
+                assert_eq!(frame.buffer().dimensions(), (6, 4));
+            }
+        }
+    }
+
+    #[test]
+    fn apng_round_trips() {
+        for (offered, kept) in [(0, 1), (3, 3), (9, 3)] {
+            let mut apng = Vec::new();
+            animation(offered).write_apng(&mut apng).unwrap();
+            let mut reader = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+            let info = reader.info();
+            assert_eq!((info.width, info.height), (6, 4));
+            let control = info.animation_control.unwrap();
+            assert_eq!(control.num_frames, kept);
+            let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+            let mut decoded = 0;
+            while decoded < kept {
+                let frame = reader.next_frame(&mut buffer).unwrap();
+                assert_eq!((frame.width, frame.height), (6, 4));
+                decoded += 1;
+            }
+        }
+    }
+
+    fn circle(geometry: [f32; 3], alpha: u8) -> Circle {
+        Circle::from_geometry(
+            ShapeKind::Circle,
+            &geometry,
+            Colour::new(200, 100, 50, alpha),
+        )
+        .unwrap()
+    }
+
+    // The first circle moves and grows, the second is only in the middle
+    // snapshot and the third never changes
+    fn svg_animation() -> String {
+        let mut animation = SvgAnimation::new(40, 30);
+        let still = circle([30.0, 20.0, 3.0], 60);
+        animation.push(&[circle([10.0, 10.0, 4.0], 50), still.clone()]);
+        animation.push(&[
+            circle([12.0, 10.0, 5.0], 50),
+            still.clone(),
+            circle([5.0, 5.0, 2.0], 80),
+        ]);
+        animation.push(&[circle([14.0, 10.0, 6.0], 70), still]);
+        let mut out = Vec::new();
+        animation.write_svg(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn animated_svg_reads_back_as_the_last_snapshot() {
+        let genome: Genome<Circle> = parse_svg(&svg_animation()).unwrap();
+        assert_eq!((genome.width, genome.height), (40, 30));
+        let shapes: Vec<_> = genome
+            .shapes
+            .iter()
+            .map(|s| (s.geometry(), s.colour().rgba()))
+            .collect();
+        assert_eq!(
+            shapes,
+            [
+                (vec![14.0, 10.0, 6.0], [200, 100, 50, 70]),
+                (vec![30.0, 20.0, 3.0], [200, 100, 50, 60]),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_shapes_are_transparent() {
+        let svg = svg_animation();
+        let elements: Vec<&str> = svg.lines().filter(|l| l.starts_with("<circle")).collect();
+        assert_eq!(elements.len(), 3);
+        // the second shape of the middle snapshot, fading in and out
+        let missing = elements[2];
+        assert!(missing.contains(r#"cx="5" cy="5" r="2""#), "{missing}");
+        assert!(missing.contains("fill-opacity:0.00;"), "{missing}");
+        assert!(
+            missing.contains(r#"<animate attributeName="fill-opacity" values="0.00;0.80;0.00""#),
+            "{missing}"
+        );
+    }
+
+    #[test]
+    fn constant_attributes_are_not_animated() {
+        let svg = svg_animation();
+        let elements: Vec<&str> = svg.lines().filter(|l| l.starts_with("<circle")).collect();
+        let moving = elements[0];
+        assert!(
+            moving.contains(r#"attributeName="cx" values="10;12;14""#),
+            "{moving}"
+        );
+        assert!(
+            moving.contains(r#"attributeName="r" values="4;5;6""#),
+            "{moving}"
+        );
+        assert!(
+            moving.contains(r#"attributeName="fill-opacity""#),
+            "{moving}"
+        );
+        for constant in ["cy", "fill"] {
+            let animated = format!(r#"attributeName="{constant}""#);
+            assert!(!moving.contains(&animated), "{moving}");
+        }
+        let still = elements[1];
+        assert!(!still.contains("<animate"), "{still}");
+        assert!(still.ends_with("/>"), "{still}");
+    }
+
+    #[test]
+    fn runs_of_equal_values_are_shortened_with_key_times() {
+        let attributes = svg_attributes(ShapeKind::Triangle, &[1.04, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(attributes, [("points", "1,2 3,4 5,6".to_string())]);
+
+        let mut animation = SvgAnimation::new(40, 30);
+        for r in [2.0, 2.0, 2.0, 2.0, 5.0] {
+            animation.push(&[circle([10.0, 10.0, r], 50)]);
+        }
+        let mut out = Vec::new();
+        animation.write_svg(&mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(
+            svg.contains(r#"attributeName="r" values="2;2;5" keyTimes="0;0.75;1""#),
+            "{svg}"
+        );
+    }
+}
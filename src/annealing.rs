@@ -0,0 +1,510 @@
+//! Simulated annealing: mutate one shape at a time, always keeping
+//! improvements and sometimes - less often as the temperature drops -
+//! accepting a change for the worse. Shapes are added over time, up to
+//! `num_shapes`.
+
+use crate::checkpoint;
+use crate::observer::{self, Observer};
+use crate::schedule::Cooling;
+use crate::{
+    Checkpoint, CoolingSchedule, CountingRng, FrameBuffer, Json, LayerCache, Optimizer, Shape,
+    Target,
+};
+use mersenne_twister_rs::MersenneTwister64;
+use rand_core::RngCore;
+use std::io;
+
+/// Settings of a `SimulatedAnnealing` run
+#[derive(Clone, Debug)]
+pub struct AnnealingConfig {
+    /// Maximum number of shapes
+    pub num_shapes: usize,
+    /// Initial temperature
+    pub temperature: f64,
+    /// Instead of `temperature`, start at the temperature at which this
+    /// fraction of moves for the worse is accepted, estimated from a sample
+    /// of moves before the run
+    pub auto_temperature: Option<f64>,
+    /// How the temperature falls, see `CoolingSchedule`
+    pub schedule: CoolingSchedule,
+    /// Factor the temperature is multiplied by every generation, for the
+    /// exponential schedule
+    pub cooling_rate: f64,
+    /// Temperature the other schedules reach at the end of the run
+    pub final_temp: f64,
+    /// Generations in the run, over which the other schedules cool
+    pub schedule_length: u64,
+    /// Generations between adding new shapes
+    pub add_interval: u64,
+    /// The temperature is raised to at least this when a shape is added
+    pub reheat_temp: f64,
+    /// Shapes between cached snapshots of the partially drawn image, see
+    /// `LayerCache`
+    pub layer_interval: usize,
+    /// Anti-alias triangle edges
+    pub antialias: bool,
+    /// After a change to the geometry of a shape, give it the best colour
+    /// for its new place, see `Target::solve_colour`
+    pub solve_colour: bool,
+    pub seed: u64,
+}
+
+impl Default for AnnealingConfig {
+    fn default() -> Self {
+        Self {
+            num_shapes: 128,
+            temperature: 1.0,
+            auto_temperature: None,
+            schedule: CoolingSchedule::Exponential,
+            cooling_rate: 0.99995,
+            final_temp: 1e-5,
+            schedule_length: 500_000,
+            add_interval: 2000,
+            reheat_temp: 0.01,
+            layer_interval: 16,
+            antialias: false,
+            solve_colour: false,
+            seed: 42,
+        }
+    }
+}
+
+impl AnnealingConfig {
+    pub fn num_shapes(mut self, num_shapes: usize) -> Self {
+        self.num_shapes = num_shapes;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn auto_temperature(mut self, acceptance: Option<f64>) -> Self {
+        self.auto_temperature = acceptance;
+        self
+    }
+
+    pub fn schedule(mut self, schedule: CoolingSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn cooling_rate(mut self, cooling_rate: f64) -> Self {
+        self.cooling_rate = cooling_rate;
+        self
+    }
+
+    pub fn final_temp(mut self, final_temp: f64) -> Self {
+        self.final_temp = final_temp;
+        self
+    }
+
+    pub fn schedule_length(mut self, generations: u64) -> Self {
+        self.schedule_length = generations;
+        self
+    }
+
+    pub fn add_interval(mut self, add_interval: u64) -> Self {
+        self.add_interval = add_interval;
+        self
+    }
+
+    pub fn reheat_temp(mut self, reheat_temp: f64) -> Self {
+        self.reheat_temp = reheat_temp;
+        self
+    }
+
+    pub fn layer_interval(mut self, layer_interval: usize) -> Self {
+        self.layer_interval = layer_interval;
+        self
+    }
+
+    pub fn antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    pub fn solve_colour(mut self, solve_colour: bool) -> Self {
+        self.solve_colour = solve_colour;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+// One annealing chain: the shapes being changed with their rendering and
+// error, the temperature, and the best shapes seen. `SimulatedAnnealing`
+// runs one chain, `ParallelTempering` several side by side.
+pub(crate) struct Chain<S: Shape> {
+    pub(crate) rng: CountingRng<MersenneTwister64>,
+    pub(crate) shapes: Vec<S>,
+    pub(crate) best_shapes: Vec<S>,
+    pub(crate) current_diff: f64,
+    pub(crate) best_diff: f64,
+    pub(crate) temperature: f64,
+    pub(crate) cooling: Cooling,
+    /// Factor the temperature is multiplied by when deciding whether to
+    /// accept a change for the worse, 1 but for the hotter replicas of
+    /// `ParallelTempering`
+    pub(crate) scale: f64,
+    // the rendering of `shapes`
+    fb: FrameBuffer,
+    layers: LayerCache,
+}
+
+impl<S: Shape> Chain<S> {
+    // Start from `shapes`, or a single random shape if there are none
+    pub(crate) fn new(
+        target: &Target,
+        config: &AnnealingConfig,
+        mut rng: CountingRng<MersenneTwister64>,
+        mut shapes: Vec<S>,
+    ) -> Self {
+        let (width, height) = (target.reference.width, target.reference.height);
+        if shapes.is_empty() {
+            shapes.push(S::random(&mut rng, width, height));
+        }
+
+        let mut fb = FrameBuffer::new(width, height);
+        fb.antialias = config.antialias;
+        fb.draw_shapes(&shapes);
+        let current_diff = target.error(&fb);
+        let mut layers = LayerCache::new(&fb, config.layer_interval);
+        layers.rebuild(&shapes);
+
+        Self {
+            rng,
+            best_shapes: shapes.clone(),
+            shapes,
+            current_diff,
+            best_diff: current_diff,
+            temperature: config.temperature,
+            cooling: Cooling::new(config, config.temperature),
+            scale: 1.0,
+            fb,
+            layers,
+        }
+    }
+
+    // The chain saved by `to_json`, with its random number generator
+    // created from `seed`
+    pub(crate) fn resume(
+        target: &Target,
+        config: &AnnealingConfig,
+        seed: u64,
+        checkpoint: &Checkpoint,
+    ) -> io::Result<Self> {
+        // the errors are restored rather than recomputed: the incrementally
+        // updated totals may differ from a fresh sum in the last bits
+        let rng = CountingRng::resume(MersenneTwister64::new(seed), checkpoint.u64("rng_draws")?);
+        let (width, height) = (target.reference.width, target.reference.height);
+        let shapes: Vec<S> =
+            checkpoint::shapes_from_json(checkpoint.field("shapes")?, width, height)?;
+        let mut chain = Self::new(target, config, rng, shapes);
+        chain.best_shapes =
+            checkpoint::shapes_from_json(checkpoint.field("best_shapes")?, width, height)?;
+        chain.current_diff = checkpoint.f64("current_diff")?;
+        chain.best_diff = checkpoint.f64("best_diff")?;
+        chain.temperature = checkpoint.f64("temperature")?;
+        // checkpoints from before the schedules were added have neither
+        let initial = checkpoint
+            .state
+            .get("initial_temperature")
+            .and_then(Json::as_f64)
+            .unwrap_or(config.temperature);
+        chain.cooling = Cooling::new(config, initial);
+        if let Some(acceptance) = checkpoint.state.get("acceptance").and_then(Json::as_f64) {
+            chain.cooling.acceptance = acceptance;
+        }
+        Ok(chain)
+    }
+
+    pub(crate) fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("rng_draws", Json::number(self.rng.draws())),
+            ("temperature", Json::number(self.temperature)),
+            ("initial_temperature", Json::number(self.cooling.initial)),
+            ("acceptance", Json::number(self.cooling.acceptance)),
+            ("current_diff", Json::number(self.current_diff)),
+            ("best_diff", Json::number(self.best_diff)),
+            ("shapes", checkpoint::shapes_to_json(&self.shapes)),
+            ("best_shapes", checkpoint::shapes_to_json(&self.best_shapes)),
+        ])
+    }
+
+    // Start cooling again from `temperature`
+    pub(crate) fn start_at(&mut self, config: &AnnealingConfig, temperature: f64) {
+        self.temperature = temperature;
+        self.cooling = Cooling::new(config, temperature);
+    }
+
+    // Cool down for generation `generation` (counting from 0), and add a
+    // shape if one is due. Returns whether a shape was added.
+    pub(crate) fn cool(
+        &mut self,
+        target: &Target,
+        config: &AnnealingConfig,
+        generation: u64,
+    ) -> bool {
+        let (width, height) = (target.reference.width, target.reference.height);
+        self.temperature = self.cooling.next(self.temperature, generation);
+
+        // Add shapes periodically
+        if generation.is_multiple_of(config.add_interval)
+            && generation > 0
+            && self.shapes.len() < config.num_shapes
+        {
+            self.shapes.push(S::random(&mut self.rng, width, height));
+            self.temperature = self.temperature.max(config.reheat_temp);
+            self.fb.draw_shapes(&self.shapes);
+            self.current_diff = target.error(&self.fb);
+            self.layers.rebuild(&self.shapes);
+            return true;
+        }
+        false
+    }
+
+    // Mutate a random shape, keeping the change if the Metropolis criterion
+    // accepts it. Returns whether the best shapes improved.
+    pub(crate) fn mutate(&mut self, target: &Target, config: &AnnealingConfig) -> bool {
+        let (width, height) = (target.reference.width, target.reference.height);
+
+        // fb holds the rendering of `shapes`; only the area covered by the
+        // mutated shape, before or after the change, is redrawn (starting from
+        // the nearest cached layer) and compared
+        let idx = (self.rng.next_u64() % self.shapes.len() as u64) as usize;
+        let original = self.shapes[idx].clone();
+        self.shapes[idx].mutate(&mut self.rng, width, height);
+        if !config.antialias {
+            self.shapes[idx].snap_to_pixels();
+        }
+
+        let region = original.bounds().union(&self.shapes[idx].bounds());
+        let saved = self.fb.copy_region(region);
+        if config.solve_colour && self.shapes[idx].geometry() != original.geometry() {
+            // solved over the shapes beneath, then the canvas is put back
+            // for scoring the change
+            self.layers
+                .render_region(&mut self.fb, &self.shapes[..idx], idx, region);
+            target.solve_colour(&mut self.shapes[idx], &mut self.fb);
+            self.fb.paste_region(region, &saved);
+        }
+        let new_diff = self.layers.update_error(
+            &mut self.fb,
+            &self.shapes,
+            idx,
+            region,
+            target,
+            self.current_diff,
+        );
+
+        // Acceptance decision
+        let temperature = self.temperature * self.scale;
+        let accept = if new_diff < self.current_diff {
+            true
+        } else if temperature > 1e-10 {
+            let delta = new_diff - self.current_diff;
+            let normalized_delta = delta / (self.current_diff + 1.0);
+            let p = (-normalized_delta / temperature).exp();
+            (self.rng.next_u64() as f64 / u64::MAX as f64) < p
+        } else {
+            false
+        };
+
+        self.cooling.record(accept);
+        if accept {
+            self.current_diff = new_diff;
+            self.layers.refresh(&self.shapes, idx, region);
+
+            if self.current_diff < self.best_diff {
+                self.best_diff = self.current_diff;
+                self.best_shapes = self.shapes.clone();
+                return true;
+            }
+        } else {
+            self.shapes[idx] = original;
+            self.fb.paste_region(region, &saved);
+        }
+        false
+    }
+
+    // The temperature at which moves for the worse are accepted with
+    // probability `acceptance` on average, from the mean of a sample of
+    // them, or None if no move made things worse. The moves are undone,
+    // leaving everything but the random number generator as it was.
+    pub(crate) fn calibrate(&mut self, target: &Target, acceptance: f64) -> Option<f64> {
+        let (width, height) = (target.reference.width, target.reference.height);
+        let mut total = 0.0;
+        let mut uphill = 0;
+        for _ in 0..CALIBRATION_MOVES {
+            let idx = (self.rng.next_u64() % self.shapes.len() as u64) as usize;
+            let original = self.shapes[idx].clone();
+            self.shapes[idx].mutate(&mut self.rng, width, height);
+            if !self.fb.antialias {
+                self.shapes[idx].snap_to_pixels();
+            }
+
+            let region = original.bounds().union(&self.shapes[idx].bounds());
+            let saved = self.fb.copy_region(region);
+            let new_diff = self.layers.update_error(
+                &mut self.fb,
+                &self.shapes,
+                idx,
+                region,
+                target,
+                self.current_diff,
+            );
+            self.shapes[idx] = original;
+            self.fb.paste_region(region, &saved);
+
+            if new_diff > self.current_diff {
+                // as in the acceptance decision of `mutate`
+                total += (new_diff - self.current_diff) / (self.current_diff + 1.0);
+                uphill += 1;
+            }
+        }
+        if uphill == 0 {
+            return None;
+        }
+        let acceptance = acceptance.clamp(1e-6, 1.0 - 1e-6);
+        Some(-(total / uphill as f64) / acceptance.ln())
+    }
+
+    // Trade the shapes being changed with `other`, keeping the temperatures
+    pub(crate) fn exchange(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.shapes, &mut other.shapes);
+        std::mem::swap(&mut self.current_diff, &mut other.current_diff);
+        std::mem::swap(&mut self.fb, &mut other.fb);
+        std::mem::swap(&mut self.layers, &mut other.layers);
+    }
+}
+
+// Moves sampled to calibrate the initial temperature
+const CALIBRATION_MOVES: usize = 500;
+
+pub struct SimulatedAnnealing<S: Shape> {
+    config: AnnealingConfig,
+    target: Target,
+    chain: Chain<S>,
+    generation: u64,
+    observers: Vec<Box<dyn Observer<S>>>,
+}
+
+impl<S: Shape> SimulatedAnnealing<S> {
+    /// Start from a single random shape
+    pub fn new(target: Target, config: AnnealingConfig) -> Self {
+        Self::from_shapes(target, config, Vec::new())
+    }
+
+    /// Start from `shapes`, e.g. an earlier result, or a single random shape
+    /// if there are none
+    pub fn from_shapes(target: Target, config: AnnealingConfig, shapes: Vec<S>) -> Self {
+        let rng = CountingRng::new(MersenneTwister64::new(config.seed));
+        let mut chain = Chain::new(&target, &config, rng, shapes);
+        if let Some(acceptance) = config.auto_temperature {
+            let temperature = chain
+                .calibrate(&target, acceptance)
+                .unwrap_or(config.temperature);
+            chain.start_at(&config, temperature);
+        }
+        Self {
+            config,
+            target,
+            chain,
+            generation: 0,
+            observers: Vec::new(),
+        }
+    }
+
+    /// Continue the run saved in `checkpoint`, which must have been made
+    /// with the same target and config
+    pub fn resume(
+        target: Target,
+        config: AnnealingConfig,
+        checkpoint: &Checkpoint,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            chain: Chain::resume(&target, &config, config.seed, checkpoint)?,
+            generation: checkpoint.generation,
+            config,
+            target,
+            observers: Vec::new(),
+        })
+    }
+
+    pub fn config(&self) -> &AnnealingConfig {
+        &self.config
+    }
+
+    pub fn temperature(&self) -> f64 {
+        self.chain.temperature
+    }
+
+    /// The temperature the run started at, which differs from
+    /// `config().temperature` if it was calibrated
+    pub fn initial_temperature(&self) -> f64 {
+        self.chain.cooling.initial
+    }
+}
+
+impl<S: Shape> Optimizer<S> for SimulatedAnnealing<S> {
+    fn step(&mut self) -> io::Result<()> {
+        let generation = self.generation;
+        self.generation += 1;
+
+        if self.chain.cool(&self.target, &self.config, generation) {
+            observer::notify(self, |o, opt| o.on_shape_added(opt))?;
+        }
+        if self.chain.mutate(&self.target, &self.config) {
+            observer::notify(self, |o, opt| o.on_improvement(opt))?;
+        }
+
+        observer::notify(self, |o, opt| o.on_generation(opt))
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn best(&self) -> &[S] {
+        &self.chain.best_shapes
+    }
+
+    fn best_fitness(&self) -> f64 {
+        self.chain.best_diff
+    }
+
+    fn current(&self) -> &[S] {
+        &self.chain.shapes
+    }
+
+    fn fitness(&self) -> f64 {
+        self.chain.current_diff
+    }
+
+    fn target(&self) -> &Target {
+        &self.target
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "current={:.4}, best={:.4}, temp={:.6}, shapes={}",
+            self.target.normalise(self.chain.current_diff),
+            self.target.normalise(self.chain.best_diff),
+            self.chain.temperature,
+            self.chain.shapes.len()
+        )
+    }
+
+    fn snapshot(&self) -> Json {
+        self.chain.to_json()
+    }
+
+    fn observers(&mut self) -> &mut Vec<Box<dyn Observer<S>>> {
+        &mut self.observers
+    }
+}
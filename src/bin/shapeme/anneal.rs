@@ -0,0 +1,273 @@
+// Simulated annealing - approximate an image with shapes.
+// Write final result to .png and .svg files and optionally intermediate
+// "frames" so that the process can be annimated.
+
+// # Basic usage
+// cargo run --release -- anneal Assets/mona_lisa_400x596.png
+//
+// # Custom output names
+// cargo run --release -- anneal Assets/mona_lisa.png -o mona.svg --output-png mona.png
+//
+// # More triangles, longer run
+// cargo run --release -- anneal image.png -s 256 -g 1000000
+//
+// # Faster cooling (converges quicker but maybe worse result)
+// cargo run --release -- anneal image.png -c 0.9999
+//
+// # No animation frames
+// cargo run --release -- anneal image.png --frame-interval 0
+//
+// # Lam's adaptive schedule, starting where half the moves for the worse
+// # are accepted
+// cargo run --release -- anneal image.png --schedule adaptive --auto-temperature 0.5
+//
+// # Replica exchange: 8 chains, each twice as hot as the one before,
+// # trading shapes every 100 generations
+// cargo run --release -- anneal image.png --replicas 8 --ladder 2 --exchange-interval 100
+//
+// # Different seed for reproducibility
+// cargo run --release -- anneal image.png --seed 12345
+//
+// # Approximate with circles instead of triangles
+// cargo run --release -- anneal image.png --shape circle
+//
+// # Checkpoint every 10000 generations, and continue after a crash
+// cargo run --release -- anneal image.png --checkpoint run.json
+// cargo run --release -- anneal --resume run.json
+//
+// # Refine an earlier result
+// cargo run --release -- anneal image.png --init Assets/triangles_annealing64.svg -t 0.01
+//
+// # Quiet mode
+// cargo run --release -- anneal image.png -q
+
+use clap::Args;
+use shapeme_rs::cli::{CommonArgs, Result};
+use shapeme_rs::{
+    AnnealingConfig, Checkpoint, Circle, CoolingSchedule, Ellipse, Optimizer, ParallelTempering,
+    Primitive, Rectangle, RotatedRectangle, Shape, ShapeKind, SimulatedAnnealing, TemperingConfig,
+    Triangle,
+};
+
+#[derive(Args, Debug)]
+pub struct AnnealArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Number of generations
+    #[arg(short, long, default_value_t = 500_000)]
+    generations: u64,
+
+    /// How the temperature falls: exponential, linear, logarithmic,
+    /// lundy-mees, or adaptive (Lam's schedule, steering the rate at which
+    /// moves are accepted)
+    #[arg(long, default_value_t = CoolingSchedule::Exponential)]
+    schedule: CoolingSchedule,
+
+    /// Cooling rate of the exponential schedule
+    #[arg(short, long, default_value_t = 0.99995)]
+    cooling_rate: f64,
+
+    /// Initial temperature
+    #[arg(short, long, default_value_t = 1.0)]
+    temperature: f64,
+
+    /// Temperature the other schedules reach after --generations
+    #[arg(long, default_value_t = 1e-5)]
+    final_temp: f64,
+
+    /// Start at the temperature at which this fraction of moves for the
+    /// worse would be accepted, measured on a sample of moves, instead of
+    /// --temperature
+    #[arg(long)]
+    auto_temperature: Option<f64>,
+
+    /// Generations between adding new shapes
+    #[arg(long, default_value_t = 2000)]
+    add_interval: u64,
+
+    /// Reheat temperature when adding shapes
+    #[arg(long, default_value_t = 0.01)]
+    reheat_temp: f64,
+
+    /// Shapes between cached snapshots of the partially drawn image; a
+    /// mutation only redraws from the nearest snapshot (0 to disable)
+    #[arg(long, default_value_t = 16)]
+    layer_interval: usize,
+
+    /// Annealing chains run in parallel at different temperatures, trading
+    /// shapes now and then (replica exchange); 1 for plain annealing
+    #[arg(long, default_value_t = 1)]
+    replicas: usize,
+
+    /// Ratio between the temperatures of neighbouring replicas
+    #[arg(long, default_value_t = 2.0)]
+    ladder: f64,
+
+    /// Generations between exchanges of shapes between replicas; the
+    /// generations and the frame, log and checkpoint intervals must be
+    /// multiples of it
+    #[arg(long, default_value_t = 100)]
+    exchange_interval: u64,
+
+    /// Worker threads for the replicas (0 = one per core)
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+
+    /// Generations between saving frames (0 to disable)
+    #[arg(long, default_value_t = 200)]
+    frame_interval: u64,
+
+    /// Generations between log output
+    #[arg(long, default_value_t = 1000)]
+    log_interval: u64,
+
+    /// Generations between checkpoints
+    #[arg(long, default_value_t = 10_000)]
+    checkpoint_interval: u64,
+}
+
+pub fn run(args: &AnnealArgs, resume: Option<Checkpoint>) -> Result<()> {
+    match args.common.shape {
+        ShapeKind::Triangle => run_with::<Triangle>(args, resume),
+        ShapeKind::Circle => run_with::<Circle>(args, resume),
+        ShapeKind::Ellipse => run_with::<Ellipse>(args, resume),
+        ShapeKind::Rectangle => run_with::<Rectangle>(args, resume),
+        ShapeKind::RotatedRectangle => run_with::<RotatedRectangle>(args, resume),
+        ShapeKind::Mixed => run_with::<Primitive>(args, resume),
+    }
+}
+
+fn run_with<S: Shape + 'static>(args: &AnnealArgs, resume: Option<Checkpoint>) -> Result<()> {
+    let common = &args.common;
+    if let Some(p) = args.auto_temperature
+        && !(p > 0.0 && p < 1.0)
+    {
+        return Err("--auto-temperature must be between 0 and 1".into());
+    }
+    if args.replicas > 1 {
+        let intervals = [
+            ("--generations", args.generations),
+            ("--frame-interval", args.frame_interval),
+            ("--log-interval", args.log_interval),
+            ("--checkpoint-interval", args.checkpoint_interval),
+        ];
+        for (name, interval) in intervals {
+            if !interval.is_multiple_of(args.exchange_interval.max(1)) {
+                return Err(format!("{name} must be a multiple of --exchange-interval").into());
+            }
+        }
+    }
+
+    let target = common.target()?;
+    let (width, height) = (target.reference.width, target.reference.height);
+
+    if !common.quiet {
+        println!("Successfully loaded image: {width}x{height}");
+        println!(
+            "Settings: shape={}, num_shapes={}, generations={}, schedule={}, cooling_rate={}",
+            common.shape, common.num_shapes, args.generations, args.schedule, args.cooling_rate
+        );
+    }
+
+    let config = AnnealingConfig::default()
+        .num_shapes(common.num_shapes)
+        .temperature(args.temperature)
+        .auto_temperature(args.auto_temperature)
+        .schedule(args.schedule)
+        .cooling_rate(args.cooling_rate)
+        .final_temp(args.final_temp)
+        .schedule_length(args.generations)
+        .add_interval(args.add_interval)
+        .reheat_temp(args.reheat_temp)
+        .layer_interval(args.layer_interval)
+        .antialias(common.antialias)
+        .solve_colour(common.solve_colour)
+        .seed(common.seed);
+
+    if args.replicas > 1 {
+        let config = TemperingConfig::default()
+            .annealing(config)
+            .replicas(args.replicas)
+            .ladder(args.ladder)
+            .exchange_interval(args.exchange_interval)
+            .threads(args.threads);
+        let mut tempering = match &resume {
+            Some(checkpoint) => ParallelTempering::<S>::resume(target, config, checkpoint)?,
+            None => {
+                let init = common.load_init(width, height)?.unwrap_or_default();
+                ParallelTempering::<S>::from_shapes(target, config, init)?
+            }
+        };
+        if !common.quiet {
+            let temperatures = tempering.temperatures();
+            println!(
+                "Replicas: {}, threads: {}, temperatures {:.6} to {:.6}",
+                args.replicas,
+                tempering.threads(),
+                temperatures[0],
+                temperatures[temperatures.len() - 1]
+            );
+        }
+        return drive(args, &mut tempering, resume.is_some());
+    }
+
+    let mut annealing = match &resume {
+        Some(checkpoint) => SimulatedAnnealing::<S>::resume(target, config, checkpoint)?,
+        None => {
+            let init = common.load_init(width, height)?.unwrap_or_default();
+            SimulatedAnnealing::<S>::from_shapes(target, config, init)
+        }
+    };
+    if !common.quiet && resume.is_none() && args.auto_temperature.is_some() {
+        println!(
+            "Calibrated initial temperature: {:.6}",
+            annealing.initial_temperature()
+        );
+    }
+    drive(args, &mut annealing, resume.is_some())
+}
+
+// Run `optimizer` to the end and save the results
+fn drive<S: Shape + 'static, O: Optimizer<S>>(
+    args: &AnnealArgs,
+    optimizer: &mut O,
+    resumed: bool,
+) -> Result<()> {
+    let common = &args.common;
+    let target = optimizer.target();
+    let (width, height) = (target.reference.width, target.reference.height);
+    if !common.quiet {
+        let diff = target.normalise(optimizer.fitness());
+        if resumed {
+            println!(
+                "Resuming at generation {}: diff {diff:.4}",
+                optimizer.generation()
+            );
+        } else {
+            println!("Initial diff: {diff:.4}");
+        }
+    }
+
+    optimizer.observers().extend(common.observers(
+        args.generations,
+        args.log_interval,
+        args.frame_interval,
+        width,
+        height,
+    )?);
+
+    let mut stop = common.stop_criteria(args.generations);
+    let reason = loop {
+        if let Some(reason) = stop.check(optimizer) {
+            break reason;
+        }
+        optimizer.step()?;
+        common.save_checkpoint(optimizer, args.checkpoint_interval)?;
+    };
+    // so that a run that was stopped early can be continued
+    common.write_checkpoint(optimizer)?;
+    optimizer.finish()?;
+
+    common.save_outputs(optimizer, reason)
+}
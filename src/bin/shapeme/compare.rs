@@ -0,0 +1,74 @@
+// Score a solution against an image with one or all of the error metrics.
+
+// # Every metric, for an SVG written by anneal or evolve
+// cargo run --release -- compare Assets/mona_lisa_head.png triangles.svg
+//
+// # One metric, weighted by the edges in the image, for any PNG
+// cargo run --release -- compare Assets/mona_lisa_head.png other.png --metric ssim --mask auto
+
+use clap::Args;
+use shapeme_rs::cli::{Result, load_mask};
+use shapeme_rs::{FrameBuffer, Genome, MetricKind, Primitive, Target};
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// The original image
+    image: String,
+
+    /// Saved genome, SVG written by anneal or evolve, or an image of the
+    /// same size
+    candidate: String,
+
+    /// Error metric: mse, euclidean, ssim or ciede2000 (default: all of them)
+    #[arg(long)]
+    metric: Option<MetricKind>,
+
+    /// Grayscale image weighting each pixel's error (white = important), or
+    /// "auto" to derive the weights from the edges in the image
+    #[arg(long)]
+    mask: Option<String>,
+
+    /// Anti-alias triangle edges when rendering the candidate
+    #[arg(long, default_value_t = false)]
+    antialias: bool,
+}
+
+pub fn run(args: &CompareArgs) -> Result<()> {
+    let reference = FrameBuffer::from_image(&image::open(&args.image)?);
+    let (width, height) = (reference.width, reference.height);
+
+    let candidate = if image::ImageFormat::from_path(&args.candidate).is_ok() {
+        FrameBuffer::from_image(&image::open(&args.candidate)?)
+    } else {
+        let genome = Genome::<Primitive>::load(&args.candidate)?;
+        let mut fb = FrameBuffer::new(genome.width, genome.height);
+        fb.antialias = args.antialias;
+        fb.draw_shapes(&genome.shapes);
+        fb
+    };
+    if (candidate.width, candidate.height) != (width, height) {
+        return Err(format!(
+            "{} is {}x{}, but {} is {width}x{height}",
+            args.candidate, candidate.width, candidate.height, args.image
+        )
+        .into());
+    }
+
+    let mask = match &args.mask {
+        Some(spec) => Some(load_mask(spec, &reference)?),
+        None => None,
+    };
+    let metrics = match args.metric {
+        Some(metric) => vec![metric],
+        None => MetricKind::ALL.to_vec(),
+    };
+
+    let mut target = Target::new(reference, metrics[0]).with_mask(mask);
+    for metric in metrics {
+        target.metric = metric.build(&target.reference);
+        let error = target.normalise(target.error(&candidate));
+        println!("{:>10}: {error:.4}", metric.name());
+    }
+    println!("{:>10}: {:.2} dB", "psnr", target.psnr(&candidate));
+    Ok(())
+}
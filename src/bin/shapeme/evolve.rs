@@ -0,0 +1,194 @@
+// Approximate an image with shapes - learned through genetic optimisation.
+
+// Write final result to .png and .svg files.
+
+// # Basic usage
+// cargo run --release -- evolve image.png
+//
+// # More triangles and larger population
+// cargo run --release -- evolve image.png -s 100 -p 100
+//
+// # Longer run with higher mutation rate
+// cargo run --release -- evolve image.png -g 50000 -m 0.1
+//
+// # Larger tournament size (more selection pressure)
+// cargo run --release -- evolve image.png -k 5
+//
+// # More elitism (preserve more top individuals)
+// cargo run --release -- evolve image.png -e 5
+//
+// # Custom output
+// cargo run --release -- evolve image.png -o result.svg --output-png result.png
+//
+// # Evolve rotated rectangles
+// cargo run --release -- evolve image.png --shape rotated-rectangle
+//
+// # Use 8 threads (results are reproducible for a given seed and thread count)
+// cargo run --release -- evolve image.png -j 8
+//
+// # 4 islands of 25, each sending its best 2 to the next every 20 generations
+// cargo run --release -- evolve image.png --islands 4 -p 25 --migration-interval 20 --migrants 2
+//
+// # Checkpoint every 100 generations, and continue after a crash
+// cargo run --release -- evolve image.png --checkpoint run.json
+// cargo run --release -- evolve --resume run.json
+//
+// # Continue from the result of an earlier run
+// cargo run --release -- evolve image.png --init Assets/triangles_annealing64.svg
+//
+// # Quiet mode, no frames
+// cargo run --release -- evolve image.png -q --frame-interval 0
+
+use clap::Args;
+use shapeme_rs::cli::{CommonArgs, Result};
+use shapeme_rs::{
+    Checkpoint, Circle, Ellipse, GeneticAlgorithm, GeneticConfig, Optimizer, Primitive, Rectangle,
+    RotatedRectangle, Shape, ShapeKind, Topology, Triangle,
+};
+
+#[derive(Args, Debug)]
+pub struct EvolveArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Population size, of each island if there are several
+    #[arg(short, long, default_value_t = 50)]
+    population: usize,
+
+    /// Sub-populations evolving separately, exchanging migrants now and
+    /// then; with several, results no longer depend on the thread count
+    #[arg(long, default_value_t = 1)]
+    islands: usize,
+
+    /// Generations between migrations (0 = never)
+    #[arg(long, default_value_t = 20)]
+    migration_interval: u64,
+
+    /// Best individuals of an island copied to each neighbour in a
+    /// migration, replacing the worst there
+    #[arg(long, default_value_t = 2)]
+    migrants: usize,
+
+    /// Which islands are neighbours: ring (each sends to the next) or full
+    /// (each sends to every other)
+    #[arg(long, default_value_t = Topology::Ring)]
+    topology: Topology,
+
+    /// Number of generations
+    #[arg(short, long, default_value_t = 10_000)]
+    generations: u64,
+
+    /// Mutation rate (0.0 - 1.0)
+    #[arg(short, long, default_value_t = 0.05)]
+    mutation_rate: f64,
+
+    /// Tournament size for selection
+    #[arg(short = 'k', long, default_value_t = 3)]
+    tournament_size: usize,
+
+    /// Number of elite individuals to preserve
+    #[arg(short, long, default_value_t = 2)]
+    elitism: usize,
+
+    /// Worker threads for breeding and evaluation (0 = one per core)
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+
+    /// Generations between saving frames (0 to disable)
+    #[arg(long, default_value_t = 100)]
+    frame_interval: u64,
+
+    /// Generations between log output
+    #[arg(long, default_value_t = 100)]
+    log_interval: u64,
+
+    /// Generations between checkpoints
+    #[arg(long, default_value_t = 100)]
+    checkpoint_interval: u64,
+}
+
+pub fn run(args: &EvolveArgs, resume: Option<Checkpoint>) -> Result<()> {
+    match args.common.shape {
+        ShapeKind::Triangle => run_with::<Triangle>(args, resume),
+        ShapeKind::Circle => run_with::<Circle>(args, resume),
+        ShapeKind::Ellipse => run_with::<Ellipse>(args, resume),
+        ShapeKind::Rectangle => run_with::<Rectangle>(args, resume),
+        ShapeKind::RotatedRectangle => run_with::<RotatedRectangle>(args, resume),
+        ShapeKind::Mixed => run_with::<Primitive>(args, resume),
+    }
+}
+
+fn run_with<S: Shape + 'static>(args: &EvolveArgs, resume: Option<Checkpoint>) -> Result<()> {
+    let common = &args.common;
+    let config = GeneticConfig::default()
+        .population(args.population)
+        .islands(args.islands)
+        .migration_interval(args.migration_interval)
+        .migrants(args.migrants)
+        .topology(args.topology)
+        .num_shapes(common.num_shapes)
+        .mutation_rate(args.mutation_rate)
+        .tournament_size(args.tournament_size)
+        .elitism(args.elitism)
+        .threads(args.threads)
+        .antialias(common.antialias)
+        .solve_colour(common.solve_colour)
+        .seed(common.seed);
+    config.validate()?;
+
+    let target = common.target()?;
+    let (width, height) = (target.reference.width, target.reference.height);
+
+    let mut ga = match &resume {
+        Some(checkpoint) => GeneticAlgorithm::<S>::resume(target, config, checkpoint)?,
+        None => {
+            let init = common.load_init(width, height)?.unwrap_or_default();
+            GeneticAlgorithm::<S>::from_shapes(target, config, init)?
+        }
+    };
+
+    if !common.quiet {
+        println!("Successfully loaded image: {width}x{height}");
+        println!(
+            "Settings: shape={}, shapes={}, population={}, generations={}, mutation_rate={}",
+            common.shape, common.num_shapes, args.population, args.generations, args.mutation_rate
+        );
+        println!("Threads: {}", ga.threads());
+        if args.islands > 1 {
+            println!(
+                "Islands: {}, migrating {} every {} generations, topology={}",
+                args.islands, args.migrants, args.migration_interval, args.topology
+            );
+        }
+        let best = ga.target().normalise(ga.best_fitness());
+        match resume {
+            Some(_) => println!(
+                "Resuming at generation {}: best fitness {best:.4}",
+                ga.generation()
+            ),
+            None => println!("Initial best fitness: {best:.4}"),
+        }
+    }
+
+    ga.observers().extend(common.observers(
+        args.generations,
+        args.log_interval,
+        args.frame_interval,
+        width,
+        height,
+    )?);
+
+    let mut stop = common.stop_criteria(args.generations);
+    let reason = loop {
+        if let Some(reason) = stop.check(&ga) {
+            break reason;
+        }
+        ga.step()?;
+        common.save_checkpoint(&ga, args.checkpoint_interval)?;
+    };
+    // so that a run that was stopped early can be continued
+    common.write_checkpoint(&ga)?;
+    ga.finish()?;
+
+    common.save_outputs(&ga, reason)
+}
@@ -0,0 +1,140 @@
+// Approximate an image by adding the best shape it can find, one at a time.
+// Write final result to .png and .svg files.
+
+// # Basic usage: 128 triangles, one per generation
+// cargo run --release -- greedy image.png
+//
+// # More shapes, searched harder
+// cargo run --release -- greedy image.png -s 300 --candidates 2000 --climb-iterations 500
+//
+// # Ellipses, as an animated GIF showing each shape being added
+// cargo run --release -- greedy image.png --shape ellipse --animation greedy.gif
+//
+// # Add to the result of an earlier run
+// cargo run --release -- greedy image.png --init Assets/triangles_annealing64.svg -s 100
+//
+// # Checkpoint every 10 shapes, and continue after a crash
+// cargo run --release -- greedy image.png --checkpoint run.json
+// cargo run --release -- greedy --resume run.json
+
+use clap::Args;
+use shapeme_rs::cli::{CommonArgs, Result};
+use shapeme_rs::{
+    Checkpoint, Circle, Ellipse, Greedy, GreedyConfig, Optimizer, Primitive, Rectangle,
+    RotatedRectangle, Shape, ShapeKind, Triangle,
+};
+
+#[derive(Args, Debug)]
+pub struct GreedyArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Random shapes tried for each new shape
+    #[arg(long, default_value_t = 500)]
+    candidates: usize,
+
+    /// Number of the best candidates refined by hill climbing
+    #[arg(long, default_value_t = 3)]
+    climbers: usize,
+
+    /// Mutations tried on each refined candidate
+    #[arg(long, default_value_t = 200)]
+    climb_iterations: usize,
+
+    /// Generations between saving frames (0 to disable)
+    #[arg(long, default_value_t = 1)]
+    frame_interval: u64,
+
+    /// Generations between log output
+    #[arg(long, default_value_t = 10)]
+    log_interval: u64,
+
+    /// Generations between checkpoints
+    #[arg(long, default_value_t = 10)]
+    checkpoint_interval: u64,
+}
+
+pub fn run(args: &GreedyArgs, resume: Option<Checkpoint>) -> Result<()> {
+    match args.common.shape {
+        ShapeKind::Triangle => run_with::<Triangle>(args, resume),
+        ShapeKind::Circle => run_with::<Circle>(args, resume),
+        ShapeKind::Ellipse => run_with::<Ellipse>(args, resume),
+        ShapeKind::Rectangle => run_with::<Rectangle>(args, resume),
+        ShapeKind::RotatedRectangle => run_with::<RotatedRectangle>(args, resume),
+        ShapeKind::Mixed => run_with::<Primitive>(args, resume),
+    }
+}
+
+fn run_with<S: Shape + 'static>(args: &GreedyArgs, resume: Option<Checkpoint>) -> Result<()> {
+    let common = &args.common;
+
+    let target = common.target()?;
+    let (width, height) = (target.reference.width, target.reference.height);
+
+    if !common.quiet {
+        println!("Successfully loaded image: {width}x{height}");
+        println!(
+            "Settings: shape={}, num_shapes={}, candidates={}, climbers={}, climb_iterations={}",
+            common.shape, common.num_shapes, args.candidates, args.climbers, args.climb_iterations
+        );
+    }
+
+    let config = GreedyConfig::default()
+        .num_shapes(common.num_shapes)
+        .candidates(args.candidates)
+        .climbers(args.climbers)
+        .climb_iterations(args.climb_iterations)
+        .antialias(common.antialias)
+        .solve_colour(common.solve_colour)
+        .seed(common.seed);
+
+    let init = common.load_init(width, height)?.unwrap_or_default();
+    // one generation per shape still to add, also when resuming
+    let generations = common.num_shapes.saturating_sub(init.len()) as u64;
+
+    let mut greedy = match &resume {
+        Some(checkpoint) => {
+            let greedy = Greedy::<S>::resume(target, config, checkpoint)?;
+            if !common.quiet {
+                println!(
+                    "Resuming at generation {}: diff {:.4}",
+                    greedy.generation(),
+                    greedy.target().normalise(greedy.fitness())
+                );
+            }
+            greedy
+        }
+        None => {
+            let greedy = Greedy::<S>::from_shapes(target, config, init);
+            if !common.quiet {
+                println!(
+                    "Initial diff: {:.4}",
+                    greedy.target().normalise(greedy.fitness())
+                );
+            }
+            greedy
+        }
+    };
+
+    greedy.observers().extend(common.observers(
+        generations,
+        args.log_interval,
+        args.frame_interval,
+        width,
+        height,
+    )?);
+
+    let mut stop = common.stop_criteria(generations);
+    let reason = loop {
+        if let Some(reason) = stop.check(&greedy) {
+            break reason;
+        }
+        greedy.step()?;
+        common.save_checkpoint(&greedy, args.checkpoint_interval)?;
+    };
+    // so that a run that was stopped early can be continued
+    common.write_checkpoint(&greedy)?;
+    greedy.finish()?;
+
+    common.save_outputs(&greedy, reason)
+}
@@ -0,0 +1,64 @@
+// Describe a saved genome, an SVG written by anneal or evolve, or a checkpoint.
+
+// cargo run --release -- info triangles.svg
+// cargo run --release -- info run.json
+
+use clap::Args;
+use shapeme_rs::cli::Result;
+use shapeme_rs::{Checkpoint, Genome, Json, Primitive, Shape, ShapeKind};
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// Saved genome, SVG or checkpoint
+    file: String,
+}
+
+pub fn run(args: &InfoArgs) -> Result<()> {
+    match Genome::<Primitive>::load(&args.file) {
+        Ok(genome) => genome_info(&genome),
+        Err(err) => match Checkpoint::load(&args.file) {
+            Ok(checkpoint) => checkpoint_info(&checkpoint),
+            Err(_) => return Err(err.into()),
+        },
+    }
+    Ok(())
+}
+
+fn genome_info(genome: &Genome<Primitive>) {
+    println!("Size: {}x{}", genome.width, genome.height);
+    let counts: Vec<String> = ShapeKind::ALL
+        .iter()
+        .filter_map(|&kind| {
+            let n = genome.shapes.iter().filter(|s| s.kind() == kind).count();
+            (n > 0).then(|| format!("{n} {kind}{}", if n == 1 { "" } else { "s" }))
+        })
+        .collect();
+    println!("Shapes: {} ({})", genome.shapes.len(), counts.join(", "));
+    if let Some(metric) = genome.metric {
+        println!("Metric: {metric}");
+    }
+    if let Some(seed) = genome.seed {
+        println!("Seed: {seed}");
+    }
+    if let Some(fitness) = genome.fitness {
+        println!("Fitness: {fitness:.4}");
+    }
+}
+
+fn checkpoint_info(checkpoint: &Checkpoint) {
+    println!("Checkpoint of: {}", checkpoint.args.join(" "));
+    println!("Next generation: {}", checkpoint.generation);
+    let len = |key| {
+        checkpoint
+            .field(key)
+            .ok()
+            .and_then(Json::as_array)
+            .map(<[Json]>::len)
+    };
+    if let Some(n) = len("shapes") {
+        println!("Shapes: {n}");
+    }
+    if let Some(n) = len("population") {
+        println!("Population: {n}");
+    }
+}
@@ -0,0 +1,108 @@
+// Approximate images with semi-transparent shapes, and work with the results.
+//
+// # Simulated annealing / genetic algorithm / greedy placement
+// cargo run --release -- anneal Assets/mona_lisa_head.png
+// cargo run --release -- evolve Assets/mona_lisa_head.png
+// cargo run --release -- greedy Assets/mona_lisa_head.png
+//
+// # Rasterise a saved solution
+// cargo run --release -- render triangles.svg -o triangles.png
+//
+// # How close is a solution to the image?
+// cargo run --release -- compare Assets/mona_lisa_head.png triangles.svg --metric ssim
+//
+// # What is in a genome, SVG or checkpoint?
+// cargo run --release -- info run.json
+//
+// # Morph one solution into another
+// cargo run --release -- morph mona.svg annunziata.svg -o morph.gif
+//
+// # Show help
+// cargo run --release -- --help
+// cargo run --release -- anneal --help
+
+mod anneal;
+mod compare;
+mod evolve;
+mod greedy;
+mod info;
+mod morph;
+mod render;
+
+use clap::{Parser, Subcommand};
+use shapeme_rs::cli::{CommonArgs, Result};
+use shapeme_rs::{Checkpoint, interrupt};
+
+#[derive(Parser, Debug)]
+#[command(name = "shapeme")]
+#[command(
+    author,
+    version,
+    about = "Approximate images using semi-transparent shapes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Approximate an image by simulated annealing
+    Anneal(anneal::AnnealArgs),
+    /// Approximate an image with a genetic algorithm
+    Evolve(evolve::EvolveArgs),
+    /// Approximate an image by adding the best shape found, one at a time
+    Greedy(greedy::GreedyArgs),
+    /// Rasterise a saved genome or SVG
+    Render(render::RenderArgs),
+    /// Score a saved solution (or any image) against an image
+    Compare(compare::CompareArgs),
+    /// Describe a saved genome, SVG or checkpoint
+    Info(info::InfoArgs),
+    /// Animate the change from one saved solution to another
+    Morph(morph::MorphArgs),
+}
+
+impl Command {
+    fn common(&mut self) -> Option<&mut CommonArgs> {
+        match self {
+            Command::Anneal(args) => Some(&mut args.common),
+            Command::Evolve(args) => Some(&mut args.common),
+            Command::Greedy(args) => Some(&mut args.common),
+            _ => None,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let mut cli = Cli::parse();
+    let mut command_line: Vec<String> = std::env::args().collect();
+
+    // a resumed run takes all its options from the checkpoint
+    let mut resume = None;
+    if let Some(path) = cli.command.common().and_then(|c| c.resume.clone()) {
+        let checkpoint = Checkpoint::load(&path)?;
+        let resumed = Cli::try_parse_from(&checkpoint.args)?;
+        if std::mem::discriminant(&resumed.command) != std::mem::discriminant(&cli.command) {
+            return Err(format!("{path} is a checkpoint of a different command").into());
+        }
+        cli = resumed;
+        command_line = checkpoint.args.clone();
+        resume = Some(checkpoint);
+    }
+    if let Some(common) = cli.command.common() {
+        common.command_line = command_line;
+        // finish the current generation and save the results on Ctrl-C
+        interrupt::install();
+    }
+
+    match cli.command {
+        Command::Anneal(args) => anneal::run(&args, resume),
+        Command::Evolve(args) => evolve::run(&args, resume),
+        Command::Greedy(args) => greedy::run(&args, resume),
+        Command::Render(args) => render::run(&args),
+        Command::Compare(args) => compare::run(&args),
+        Command::Info(args) => info::run(&args),
+        Command::Morph(args) => morph::run(&args),
+    }
+}
@@ -0,0 +1,129 @@
+// Morph from one saved solution to another, for title sequences and the like.
+
+// # Mona Lisa turning into the Annunziata, as a GIF
+// cargo run --release -- morph mona.svg annunziata.svg -o morph.gif
+//
+// # Two seconds at 30 fps, holding each end for half a second, as APNG
+// cargo run --release -- morph mona.svg annunziata.svg -o morph.apng --frames 60 --hold 15 --fps 30
+//
+// # The frames as PNG files, twice the size, for a video editor
+// cargo run --release -- morph mona.svg annunziata.svg --frames-dir morph --scale 2
+
+use clap::Args;
+use shapeme_rs::cli::Result;
+use shapeme_rs::{Animation, FrameBuffer, Genome, Morph, Primitive};
+use std::fs;
+use std::path::Path;
+
+#[derive(Args, Debug)]
+pub struct MorphArgs {
+    /// Saved genome or SVG to start from; its size is that of the output
+    from: String,
+
+    /// Saved genome or SVG to end with, scaled to fit
+    to: String,
+
+    /// Animated GIF, or APNG if the name ends in .png or .apng (default:
+    /// morph.gif, unless --frames-dir is given)
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Also save the frames as PNG files to this directory
+    #[arg(long)]
+    frames_dir: Option<String>,
+
+    /// Number of frames from one solution to the other
+    #[arg(long, default_value_t = 60)]
+    frames: usize,
+
+    /// Extra frames showing each solution before and after the morph
+    #[arg(long, default_value_t = 0)]
+    hold: usize,
+
+    /// Frames per second of the animation
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    /// Move at constant speed instead of easing in and out
+    #[arg(long, default_value_t = false)]
+    linear: bool,
+
+    /// Size of the output relative to the first solution
+    #[arg(long, default_value_t = 1.0)]
+    scale: f32,
+
+    /// Anti-alias triangle edges
+    #[arg(long, default_value_t = false)]
+    antialias: bool,
+
+    /// GIF palette quantisation, from 1 (best colours, slowest) to 30
+    #[arg(long, default_value_t = 10)]
+    gif_speed: i32,
+}
+
+pub fn run(args: &MorphArgs) -> Result<()> {
+    let from = Genome::<Primitive>::load(&args.from)?;
+    let to = Genome::<Primitive>::load(&args.to)?;
+    let output = match (&args.output, &args.frames_dir) {
+        (Some(path), _) => Some(path.as_str()),
+        (None, Some(_)) => None,
+        (None, None) => Some("morph.gif"),
+    };
+    if let Some(path) = output
+        && path.to_ascii_lowercase().ends_with(".svg")
+    {
+        return Err("morphs can only be saved as GIF or APNG".into());
+    }
+    if args.frames < 2 {
+        return Err("a morph needs at least 2 frames".into());
+    }
+
+    let size = |v: u16| (v as f32 * args.scale).round();
+    let (width, height) = (size(from.width), size(from.height));
+    if !(1.0..=u16::MAX as f32).contains(&width) || !(1.0..=u16::MAX as f32).contains(&height) {
+        return Err(format!("cannot morph at scale {}", args.scale).into());
+    }
+    let mut fb = FrameBuffer::new(width as u16, height as u16);
+    fb.antialias = args.antialias;
+    fb.scale = args.scale;
+
+    if let Some(dir) = &args.frames_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let total = args.frames + 2 * args.hold;
+    let mut animation = Animation::new(fb.width, fb.height)
+        .fps(args.fps)
+        .max_frames(total)
+        .gif_speed(args.gif_speed);
+
+    let morph = Morph::new(&from, &to);
+    for i in 0..total {
+        let t = (i.saturating_sub(args.hold) as f32 / (args.frames - 1) as f32).min(1.0);
+        // smoothstep, starting and stopping gently
+        let t = if args.linear {
+            t
+        } else {
+            t * t * (3.0 - 2.0 * t)
+        };
+        morph.render(&mut fb, t);
+        if let Some(dir) = &args.frames_dir {
+            fb.save_png(Path::new(dir).join(format!("frame_{i:06}.png")))?;
+        }
+        if output.is_some() {
+            animation.push(&fb);
+        }
+    }
+
+    if let Some(path) = output {
+        animation.save(path)?;
+        println!(
+            "Morphed {} shapes into {} over {total} frames: {path}",
+            from.shapes.len(),
+            to.shapes.len()
+        );
+    }
+    if let Some(dir) = &args.frames_dir {
+        println!("Saved {total} frames to: {dir}");
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+// Rasterise a saved solution with the library's own rasteriser.
+
+// # Render an SVG written by anneal or evolve
+// cargo run --release -- render triangles.svg -o triangles.png
+//
+// # Render a saved genome with anti-aliased edges
+// cargo run --release -- render run.genome --antialias
+//
+// # Four times the size for print, on a white background
+// cargo run --release -- render triangles.svg --scale 4 --background ffffff
+//
+// # A thumbnail 100 pixels wide
+// cargo run --release -- render triangles.svg --width 100 -o thumb.png
+
+use clap::Args;
+use shapeme_rs::cli::Result;
+use shapeme_rs::{FrameBuffer, Genome, Primitive};
+use std::path::Path;
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    /// Saved genome, or SVG written by anneal or evolve
+    input: String,
+
+    /// Output PNG path (default: the input with a .png extension)
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Anti-alias triangle edges
+    #[arg(long, default_value_t = false)]
+    antialias: bool,
+
+    /// Size of the output relative to the image the shapes were made for
+    #[arg(long, default_value_t = 1.0, conflicts_with = "width")]
+    scale: f32,
+
+    /// Width of the output in pixels, keeping the aspect ratio
+    #[arg(long)]
+    width: Option<u16>,
+
+    /// Background colour as hex RGB, e.g. ffffff for white
+    #[arg(long, default_value = "000000", value_parser = parse_rgb)]
+    background: [u8; 3],
+}
+
+// "rrggbb", optionally with a leading '#'
+fn parse_rgb(s: &str) -> std::result::Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("expected a colour as rrggbb, got {s}")),
+    }
+}
+
+pub fn run(args: &RenderArgs) -> Result<()> {
+    let genome = Genome::<Primitive>::load(&args.input)?;
+    let output = match &args.output {
+        Some(path) => path.clone(),
+        None => Path::new(&args.input)
+            .with_extension("png")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    let scale = match args.width {
+        Some(width) => width as f32 / genome.width as f32,
+        None => args.scale,
+    };
+    let size = |v: u16| (v as f32 * scale).round();
+    let (width, height) = (size(genome.width), size(genome.height));
+    if !(1.0..=u16::MAX as f32).contains(&width) || !(1.0..=u16::MAX as f32).contains(&height) {
+        return Err(format!(
+            "cannot render {}x{} at scale {scale}",
+            genome.width, genome.height
+        )
+        .into());
+    }
+
+    let mut fb = FrameBuffer::new(width as u16, height as u16);
+    fb.antialias = args.antialias;
+    fb.scale = scale;
+    fb.background = args.background;
+    fb.draw_shapes(&genome.shapes);
+    fb.save_png(&output)?;
+
+    println!(
+        "Rendered {} shapes at {}x{} to: {output}",
+        genome.shapes.len(),
+        fb.width,
+        fb.height
+    );
+    Ok(())
+}
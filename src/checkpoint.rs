@@ -0,0 +1,323 @@
+//! Periodic snapshots of a run, so that it can be resumed after the process dies.
+
+use crate::json::Json;
+use crate::{Colour, Shape, ShapeKind};
+use rand_core::{RngCore, impls};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const VERSION: u64 = 1;
+
+/// Random number generator that counts the values drawn from it.
+///
+/// The state of a generator is restored by re-seeding it and skipping the
+/// same number of draws, which works for any generator.
+pub struct CountingRng<R> {
+    rng: R,
+    draws: u64,
+}
+
+impl<R: RngCore> CountingRng<R> {
+    pub fn new(rng: R) -> Self {
+        Self { rng, draws: 0 }
+    }
+
+    /// `rng` advanced by `draws` values, i.e. in the state it had when
+    /// `draws()` returned `draws`
+    pub fn resume(mut rng: R, draws: u64) -> Self {
+        for _ in 0..draws {
+            rng.next_u64();
+        }
+        Self { rng, draws }
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+}
+
+// every value goes through next_u64 so that draws() is the whole story
+impl<R: RngCore> RngCore for CountingRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+/// The state of an interrupted run
+pub struct Checkpoint {
+    /// Command line the run was started with
+    pub args: Vec<String>,
+    /// The next generation to run
+    pub generation: u64,
+    /// Optimiser specific state
+    pub state: Json,
+}
+
+pub(crate) fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to `path`. The file is replaced atomically, so a
+    /// crash while saving leaves the previous checkpoint intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let json = Json::object(vec![
+            ("version", Json::number(VERSION)),
+            (
+                "args",
+                Json::Array(self.args.iter().cloned().map(Json::String).collect()),
+            ),
+            ("generation", Json::number(self.generation)),
+            ("state", self.state.clone()),
+        ]);
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, format!("{json}\n"))?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let json = Json::parse(&fs::read_to_string(path)?).map_err(invalid)?;
+        match json.get("version").and_then(Json::as_u64) {
+            Some(VERSION) => {}
+            Some(v) => return Err(invalid(format!("unsupported checkpoint version {v}"))),
+            None => return Err(invalid("not a checkpoint")),
+        }
+        let args = json
+            .get("args")
+            .and_then(Json::as_array)
+            .and_then(|a| a.iter().map(|s| s.as_str().map(String::from)).collect())
+            .ok_or_else(|| invalid("checkpoint without args"))?;
+        let generation = json
+            .get("generation")
+            .and_then(Json::as_u64)
+            .ok_or_else(|| invalid("checkpoint without generation"))?;
+        let state = json.get("state").cloned().unwrap_or(Json::Null);
+        Ok(Self {
+            args,
+            generation,
+            state,
+        })
+    }
+
+    /// Field `key` of the optimiser state
+    pub fn field(&self, key: &str) -> io::Result<&Json> {
+        self.state
+            .get(key)
+            .ok_or_else(|| invalid(format!("checkpoint without {key}")))
+    }
+
+    /// Number field `key` of the optimiser state
+    pub fn f64(&self, key: &str) -> io::Result<f64> {
+        self.field(key)?
+            .as_f64()
+            .ok_or_else(|| invalid(format!("{key} is not a number")))
+    }
+
+    /// Integer field `key` of the optimiser state
+    pub fn u64(&self, key: &str) -> io::Result<u64> {
+        self.field(key)?
+            .as_u64()
+            .ok_or_else(|| invalid(format!("{key} is not an integer")))
+    }
+}
+
+pub fn shape_to_json<S: Shape>(shape: &S) -> Json {
+    Json::object(vec![
+        ("kind", Json::String(shape.kind().to_string())),
+        (
+            "geometry",
+            Json::Array(shape.geometry().into_iter().map(Json::number).collect()),
+        ),
+        (
+            "colour",
+            Json::Array(shape.colour().rgba().map(Json::number).to_vec()),
+        ),
+    ])
+}
+
+/// A shape saved with `shape_to_json`, made valid for a `width` x `height`
+/// canvas as it may have been edited by hand
+pub fn shape_from_json<S: Shape>(json: &Json, width: u16, height: u16) -> io::Result<S> {
+    let kind: ShapeKind = json
+        .get("kind")
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid("shape without kind"))?
+        .parse()
+        .map_err(invalid)?;
+    let geometry: Vec<f32> = json
+        .get("geometry")
+        .and_then(Json::as_array)
+        .and_then(|a| a.iter().map(Json::as_f32).collect())
+        .ok_or_else(|| invalid("shape without geometry"))?;
+    let rgba: Vec<u8> = json
+        .get("colour")
+        .and_then(Json::as_array)
+        .and_then(|a| a.iter().map(|v| v.as_u64()?.try_into().ok()).collect())
+        .ok_or_else(|| invalid("shape without colour"))?;
+    let &[r, g, b, alpha] = rgba.as_slice() else {
+        return Err(invalid("colour must be [r, g, b, alpha]"));
+    };
+    let mut shape = S::from_geometry(kind, &geometry, Colour::new(r, g, b, alpha))
+        .ok_or_else(|| invalid(format!("unexpected {kind} geometry {geometry:?}")))?;
+    shape.normalise(width, height);
+    Ok(shape)
+}
+
+pub fn shapes_to_json<S: Shape>(shapes: &[S]) -> Json {
+    Json::Array(shapes.iter().map(shape_to_json).collect())
+}
+
+pub fn shapes_from_json<S: Shape>(json: &Json, width: u16, height: u16) -> io::Result<Vec<S>> {
+    json.as_array()
+        .ok_or_else(|| invalid("expected a list of shapes"))?
+        .iter()
+        .map(|shape| shape_from_json(shape, width, height))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AnnealingConfig, FrameBuffer, GeneticAlgorithm, GeneticConfig, Greedy, GreedyConfig,
+        MetricKind, Optimizer, Primitive, SimulatedAnnealing, Target,
+    };
+    use mersenne_twister_rs::MersenneTwister64;
+
+    fn target() -> Target {
+        let mut reference = FrameBuffer::new(24, 16);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % 24, i / 24);
+            px.copy_from_slice(&[(x * 10) as u8, (y * 15) as u8, ((x * y) % 256) as u8]);
+        }
+        Target::new(reference, MetricKind::Euclidean)
+    }
+
+    // what the command line saves and loads, including the trip through text
+    fn checkpoint<S: Shape, O: Optimizer<S>>(optimizer: &O) -> Checkpoint {
+        let state = Json::parse(&optimizer.snapshot().to_string()).unwrap();
+        Checkpoint {
+            args: Vec::new(),
+            generation: optimizer.generation(),
+            state,
+        }
+    }
+
+    fn run<S: Shape, O: Optimizer<S>>(optimizer: &mut O, generations: u64) {
+        for _ in 0..generations {
+            optimizer.step().unwrap();
+        }
+    }
+
+    fn assert_same<S: Shape, O: Optimizer<S>>(a: &O, b: &O) {
+        let shapes = |shapes: &[S]| -> Vec<_> {
+            shapes
+                .iter()
+                .map(|s| (s.kind(), s.geometry(), s.colour().rgba()))
+                .collect()
+        };
+        assert_eq!(a.generation(), b.generation());
+        assert_eq!(a.fitness(), b.fitness());
+        assert_eq!(a.best_fitness(), b.best_fitness());
+        assert_eq!(shapes(a.current()), shapes(b.current()));
+        assert_eq!(shapes(a.best()), shapes(b.best()));
+    }
+
+    #[test]
+    fn counting_rng_resumes_where_it_was() {
+        let mut rng = CountingRng::new(MersenneTwister64::new(5));
+        for _ in 0..17 {
+            rng.next_u64();
+        }
+        let mut resumed = CountingRng::resume(MersenneTwister64::new(5), rng.draws());
+        assert_eq!(resumed.draws(), 17);
+        for _ in 0..10 {
+            assert_eq!(resumed.next_u64(), rng.next_u64());
+        }
+    }
+
+    #[test]
+    fn resumed_annealing_matches_an_uninterrupted_run() {
+        let config = AnnealingConfig::default()
+            .num_shapes(8)
+            .add_interval(50)
+            .layer_interval(3)
+            .seed(11);
+        let mut straight = SimulatedAnnealing::<Primitive>::new(target(), config.clone());
+        run(&mut straight, 700);
+
+        let mut first = SimulatedAnnealing::<Primitive>::new(target(), config.clone());
+        run(&mut first, 300);
+        let mut resumed =
+            SimulatedAnnealing::<Primitive>::resume(target(), config, &checkpoint(&first)).unwrap();
+        assert_same(&first, &resumed);
+        run(&mut resumed, 400);
+        assert_same(&straight, &resumed);
+        assert_eq!(straight.temperature(), resumed.temperature());
+    }
+
+    #[test]
+    fn resumed_genetic_algorithm_matches_an_uninterrupted_run() {
+        let config = GeneticConfig::default()
+            .population(6)
+            .num_shapes(5)
+            .threads(2)
+            .seed(3);
+        let mut straight = GeneticAlgorithm::<Primitive>::new(target(), config.clone()).unwrap();
+        run(&mut straight, 12);
+
+        let mut first = GeneticAlgorithm::<Primitive>::new(target(), config.clone()).unwrap();
+        run(&mut first, 5);
+        let mut resumed =
+            GeneticAlgorithm::<Primitive>::resume(target(), config, &checkpoint(&first)).unwrap();
+        run(&mut resumed, 7);
+        assert_same(&straight, &resumed);
+    }
+
+    #[test]
+    fn resumed_greedy_run_matches_an_uninterrupted_run() {
+        let config = GreedyConfig::default()
+            .num_shapes(10)
+            .candidates(30)
+            .climb_iterations(20)
+            .seed(9);
+        let mut straight = Greedy::<Primitive>::new(target(), config.clone());
+        run(&mut straight, 8);
+
+        let mut first = Greedy::<Primitive>::new(target(), config.clone());
+        run(&mut first, 3);
+        let mut resumed =
+            Greedy::<Primitive>::resume(target(), config, &checkpoint(&first)).unwrap();
+        assert_same(&first, &resumed);
+        run(&mut resumed, 5);
+        assert_same(&straight, &resumed);
+    }
+
+    #[test]
+    fn loaded_shapes_are_normalised() {
+        let json = Json::parse(
+            r#"[{"kind":"rectangle","geometry":[200,200,10,10],"colour":[1,2,3,50]},
+                {"kind":"triangle","geometry":[1,9,2,5,3,1],"colour":[1,2,3,50]}]"#,
+        )
+        .unwrap();
+        let shapes: Vec<Primitive> = shapes_from_json(&json, 100, 100).unwrap();
+        assert_eq!(shapes[0].geometry(), vec![10.0, 10.0, 99.0, 99.0]);
+        assert_eq!(shapes[1].geometry(), vec![3.0, 1.0, 2.0, 5.0, 1.0, 9.0]);
+
+        let json =
+            Json::parse(r#"[{"kind":"circle","geometry":[1,2],"colour":[1,2,3,50]}]"#).unwrap();
+        assert!(shapes_from_json::<Primitive>(&json, 100, 100).is_err());
+    }
+}
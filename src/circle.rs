@@ -0,0 +1,166 @@
+use crate::primitive::{Footprint, in_range, to_u16};
+use crate::{Colour, FrameBuffer, Rect, Shape, ShapeKind, jitter, rand_between};
+use rand_core::RngCore;
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Clone)]
+pub struct Circle {
+    centre: (u16, u16),
+    radius: u16,
+    colour: Colour,
+}
+
+impl fmt::Display for Circle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.centre;
+        write!(f, "Circle ({x},{y}) r={} {}", self.radius, self.colour)
+    }
+}
+
+// largest radius a random circle starts out with
+fn max_radius(width: u16, height: u16) -> u16 {
+    (width.min(height) / 4).max(1)
+}
+
+impl Circle {
+    pub(crate) fn footprint(&self) -> Footprint {
+        let r = self.radius as f32;
+        Footprint {
+            centre: (self.centre.0 as f32, self.centre.1 as f32),
+            half_size: (r, r),
+        }
+    }
+
+    // circle with the mean radius of the footprint
+    pub(crate) fn from_footprint(fp: &Footprint, colour: Colour) -> Self {
+        Circle {
+            centre: (to_u16(fp.centre.0), to_u16(fp.centre.1)),
+            radius: to_u16((fp.half_size.0 + fp.half_size.1) / 2.0),
+            colour,
+        }
+    }
+}
+
+impl Shape for Circle {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+        let x = rand_between(rng, 0, width as i64 - 1) as u16;
+        let y = rand_between(rng, 0, height as i64 - 1) as u16;
+        let radius = rand_between(rng, 1, max_radius(width, height) as i64) as u16;
+        let mut c = Circle {
+            centre: (x, y),
+            radius,
+            colour: Colour::random(rng),
+        };
+        c.normalise(width, height);
+        c
+    }
+
+    // mutate: move the centre and grow or shrink the radius
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    ) {
+        self.centre.0 = jitter(rng, self.centre.0, delta, 0, width as i64 - 1);
+        self.centre.1 = jitter(rng, self.centre.1, delta, 0, height as i64 - 1);
+        self.radius = jitter(rng, self.radius, delta, 1, width.max(height) as i64);
+        self.normalise(width, height);
+    }
+
+    // keep the centre on the canvas and the radius non-zero
+    fn normalise(&mut self, width: u16, height: u16) {
+        self.centre.0 = self.centre.0.min(width.saturating_sub(1));
+        self.centre.1 = self.centre.1.min(height.saturating_sub(1));
+        self.radius = self.radius.clamp(1, width.max(height).max(1));
+    }
+
+    fn colour(&self) -> &Colour {
+        &self.colour
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        &mut self.colour
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        let (x, y) = self.centre;
+        let r = self.radius as f32;
+        fb.fill_ellipse(x as f32, y as f32, r, r, &self.colour);
+    }
+
+    fn bounds(&self) -> Rect {
+        let (x, y) = (self.centre.0 as f32, self.centre.1 as f32);
+        let r = self.radius as f32;
+        Rect::around(x - r, y - r, x + r, y + r)
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (x, y) = self.centre;
+        writeln!(
+            w,
+            r#"<circle cx="{x}" cy="{y}" r="{}" style="{}"/>"#,
+            self.radius,
+            self.colour.svg_style()
+        )
+    }
+
+    fn kind(&self) -> ShapeKind {
+        ShapeKind::Circle
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        vec![
+            self.centre.0 as f32,
+            self.centre.1 as f32,
+            self.radius as f32,
+        ]
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        match (kind, geometry) {
+            (ShapeKind::Circle, &[x, y, r]) if in_range(geometry) && r >= 1.0 => Some(Circle {
+                centre: (to_u16(x), to_u16(y)),
+                radius: to_u16(r),
+                colour,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_round_trips() {
+        let c = Circle::from_geometry(
+            ShapeKind::Circle,
+            &[12.0, 7.0, 4.0],
+            Colour::new(9, 8, 7, 60),
+        )
+        .unwrap();
+        assert_eq!(c.geometry(), vec![12.0, 7.0, 4.0]);
+        let copy = Circle::from_geometry(c.kind(), &c.geometry(), c.colour().clone()).unwrap();
+        assert_eq!(copy.geometry(), c.geometry());
+        assert_eq!(copy.colour().rgba(), [9, 8, 7, 60]);
+    }
+
+    #[test]
+    fn rejects_bad_geometry() {
+        let colour = Colour::new(0, 0, 0, 50);
+        for geometry in [
+            &[1.0, 2.0][..],
+            &[1.0, 2.0, 0.0],
+            &[-1.0, 2.0, 3.0],
+            &[1.0, 2.0, f32::NAN],
+            &[1.0, 70_000.0, 3.0],
+        ] {
+            assert!(Circle::from_geometry(ShapeKind::Circle, geometry, colour.clone()).is_none());
+        }
+        assert!(Circle::from_geometry(ShapeKind::Ellipse, &[1.0, 2.0, 3.0], colour).is_none());
+    }
+}
@@ -0,0 +1,465 @@
+//! Command line options and the plumbing around an optimisation run that is
+//! the same for every optimiser: loading the input, saving frames,
+//! checkpoints and the final outputs.
+
+use crate::animation::{Animation, AnimationRecorder, SvgAnimation, SvgAnimationRecorder};
+use crate::{
+    Checkpoint, ConsoleLogger, CsvLogger, FrameBuffer, FrameSaver, Genome, JsonLinesLogger, Mask,
+    MetricKind, Observer, Optimizer, Shape, ShapeKind, StopCriteria, StopReason, Target, save_svg,
+};
+use clap::Args;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// The mask given on the command line: "auto" for one derived from the
+/// edges of `reference`, otherwise the path of a grayscale image
+pub fn load_mask(spec: &str, reference: &FrameBuffer) -> Result<Mask> {
+    Ok(match spec {
+        "auto" => Mask::from_edges(reference),
+        path => Mask::from_image(&image::open(path)?, reference.width, reference.height),
+    })
+}
+
+/// A duration given as seconds, or with an s, m or h suffix: "90", "15m"
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let (number, unit) = match s.trim().char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s.trim()[..i], c),
+        _ => (s.trim(), 's'),
+    };
+    let seconds = match unit {
+        's' => 1.0,
+        'm' => 60.0,
+        'h' => 3600.0,
+        _ => return Err(format!("unknown unit '{unit}' in {s}, expected s, m or h")),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * seconds).ok())
+        .ok_or_else(|| format!("expected a duration such as 90, 90s, 15m or 2h, got {s}"))
+}
+
+/// Options shared by the optimisers
+#[derive(Args, Debug, Clone)]
+pub struct CommonArgs {
+    /// Input image path
+    #[arg(required_unless_present = "resume", default_value = "")]
+    pub input: String,
+
+    /// Output SVG path
+    #[arg(short, long, default_value = "triangles.svg")]
+    pub output: String,
+
+    /// Output PNG path
+    #[arg(long, default_value = "triangles.png")]
+    pub output_png: String,
+
+    /// Also save the solution as a genome that can be reloaded (JSON if the
+    /// name ends in .json, compact binary otherwise)
+    #[arg(long)]
+    pub output_genome: Option<String>,
+
+    /// Number of shapes (for anneal the maximum, as shapes are added over time)
+    #[arg(short = 's', long, default_value_t = 128)]
+    pub num_shapes: usize,
+
+    /// Primitive to approximate with: triangle, circle, ellipse, rectangle,
+    /// rotated-rectangle or mixed
+    #[arg(long, default_value = "triangle")]
+    pub shape: ShapeKind,
+
+    /// Start from the shapes in an SVG written by this program (or a saved
+    /// genome) instead of random ones
+    #[arg(long)]
+    pub init: Option<String>,
+
+    /// Error metric: mse, euclidean, ssim or ciede2000 (perceptual, CIELAB)
+    #[arg(long, default_value = "euclidean")]
+    pub metric: MetricKind,
+
+    /// Grayscale image weighting each pixel's error (white = important), or
+    /// "auto" to derive the weights from the edges in the input image
+    #[arg(long)]
+    pub mask: Option<String>,
+
+    /// Anti-alias triangle edges so the PNG matches the SVG rendering
+    #[arg(long, default_value_t = false)]
+    pub antialias: bool,
+
+    /// Give each shape the best colour for its place whenever its geometry
+    /// changes, instead of leaving colour to random mutation
+    #[arg(long, default_value_t = false)]
+    pub solve_colour: bool,
+
+    /// Stop after this long, e.g. 90 (seconds), 15m or 2h
+    #[arg(long, value_parser = parse_duration)]
+    pub time_limit: Option<Duration>,
+
+    /// Stop once the best fitness (per pixel, as in the logs) is at most this
+    #[arg(long)]
+    pub target_fitness: Option<f64>,
+
+    /// Stop once the best solution has at least this PSNR in dB
+    #[arg(long)]
+    pub target_psnr: Option<f64>,
+
+    /// Stop after this many generations without improving the best solution
+    #[arg(long)]
+    pub stagnation: Option<u64>,
+
+    /// Random seed
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+
+    /// Directory for animation frames as PNG files (empty to disable;
+    /// default "frames" unless --animation is given)
+    #[arg(long)]
+    pub frames_dir: Option<String>,
+
+    /// Write an animation of the run, sampled every frame interval: an
+    /// animated SVG if the name ends in .svg, APNG if it ends in .png or
+    /// .apng, GIF otherwise
+    #[arg(long)]
+    pub animation: Option<String>,
+
+    /// Frames per second of the animation
+    #[arg(long, default_value_t = 10)]
+    pub fps: u32,
+
+    /// Maximum number of frames in the animation, rounded down to an even
+    /// number; longer runs are sampled less often
+    #[arg(long, default_value_t = 200)]
+    pub max_frames: usize,
+
+    /// GIF palette quantisation, from 1 (best colours, slowest) to 30
+    #[arg(long, default_value_t = 10)]
+    pub gif_speed: i32,
+
+    /// Write the fitness every log interval to this CSV file
+    #[arg(long)]
+    pub csv: Option<String>,
+
+    /// Write progress events (improvements, added shapes, ...) to this
+    /// file, one JSON object per line
+    #[arg(long)]
+    pub events: Option<String>,
+
+    /// Checkpoint file, rewritten every `checkpoint_interval` generations
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+
+    /// Continue the run saved in a checkpoint; all other options are taken
+    /// from the checkpoint
+    #[arg(long)]
+    pub resume: Option<String>,
+
+    /// Quiet mode - suppress progress output
+    #[arg(short, long, default_value_t = false)]
+    pub quiet: bool,
+
+    /// The command line the options were parsed from, saved in checkpoints
+    #[arg(skip)]
+    pub command_line: Vec<String>,
+}
+
+impl CommonArgs {
+    /// The input image and how approximations of it are scored
+    pub fn target(&self) -> Result<Target> {
+        let img = image::open(Path::new(&self.input))?;
+        let reference = FrameBuffer::from_image(&img);
+        let mask = match &self.mask {
+            Some(spec) => Some(load_mask(spec, &reference)?),
+            None => None,
+        };
+        Ok(Target::new(reference, self.metric).with_mask(mask))
+    }
+
+    /// A blank canvas of the given size, rasterising as requested
+    pub fn framebuffer(&self, width: u16, height: u16) -> FrameBuffer {
+        let mut fb = FrameBuffer::new(width, height);
+        fb.antialias = self.antialias;
+        fb
+    }
+
+    /// The `--init` shapes, which must have been made for an image of the
+    /// same size
+    pub fn load_init<S: Shape>(&self, width: u16, height: u16) -> Result<Option<Vec<S>>> {
+        let Some(path) = &self.init else {
+            return Ok(None);
+        };
+        let genome = Genome::<S>::load(path)?;
+        if (genome.width, genome.height) != (width, height) {
+            return Err(format!(
+                "{path} is {}x{}, but the input image is {width}x{height}",
+                genome.width, genome.height
+            )
+            .into());
+        }
+        Ok(Some(genome.shapes))
+    }
+
+    /// When to stop a run of at most `generations` generations
+    pub fn stop_criteria(&self, generations: u64) -> StopCriteria {
+        StopCriteria::new(generations)
+            .time_limit(self.time_limit)
+            .target_fitness(self.target_fitness)
+            .target_psnr(self.target_psnr)
+            .stagnation(self.stagnation)
+            .antialias(self.antialias)
+    }
+
+    /// The observers logging the progress and saving frames, as requested
+    /// on the command line
+    pub fn observers<S: Shape + 'static>(
+        &self,
+        generations: u64,
+        log_interval: u64,
+        frame_interval: u64,
+        width: u16,
+        height: u16,
+    ) -> Result<Vec<Box<dyn Observer<S>>>> {
+        let mut observers: Vec<Box<dyn Observer<S>>> = Vec::new();
+        if !self.quiet {
+            observers.push(Box::new(ConsoleLogger::new(log_interval, generations)));
+        }
+        let frames_dir = match (&self.frames_dir, &self.animation) {
+            (Some(dir), _) => dir.as_str(),
+            (None, Some(_)) => "",
+            (None, None) => "frames",
+        };
+        if !frames_dir.is_empty() && frame_interval > 0 {
+            let fb = self.framebuffer(width, height);
+            observers.push(Box::new(FrameSaver::new(frames_dir, frame_interval, fb)?));
+        }
+        if let Some(path) = &self.animation
+            && path.to_ascii_lowercase().ends_with(".svg")
+        {
+            let animation = SvgAnimation::new(width, height)
+                .fps(self.fps)
+                .max_frames(self.max_frames);
+            observers.push(Box::new(SvgAnimationRecorder::new(
+                animation,
+                path,
+                frame_interval,
+            )));
+        } else if let Some(path) = &self.animation {
+            let animation = Animation::new(width, height)
+                .fps(self.fps)
+                .max_frames(self.max_frames)
+                .gif_speed(self.gif_speed);
+            let fb = self.framebuffer(width, height);
+            observers.push(Box::new(AnimationRecorder::new(
+                animation,
+                path,
+                frame_interval,
+                fb,
+            )));
+        }
+        if let Some(path) = &self.csv {
+            let out = BufWriter::new(File::create(path)?);
+            observers.push(Box::new(CsvLogger::new(out, log_interval)?));
+        }
+        if let Some(path) = &self.events {
+            let out = BufWriter::new(File::create(path)?);
+            observers.push(Box::new(JsonLinesLogger::new(out, log_interval)));
+        }
+        Ok(observers)
+    }
+
+    /// Write a checkpoint of `optimizer` if one is due every `interval`
+    /// generations; the state is only built when it is
+    pub fn save_checkpoint<S: Shape>(
+        &self,
+        optimizer: &dyn Optimizer<S>,
+        interval: u64,
+    ) -> Result<()> {
+        if interval > 0 && optimizer.generation().is_multiple_of(interval) {
+            self.write_checkpoint(optimizer)?;
+        }
+        Ok(())
+    }
+
+    /// Write a checkpoint of `optimizer` now, if checkpoints are enabled
+    pub fn write_checkpoint<S: Shape>(&self, optimizer: &dyn Optimizer<S>) -> Result<()> {
+        if let Some(path) = &self.checkpoint {
+            Checkpoint {
+                args: self.command_line.clone(),
+                generation: optimizer.generation(),
+                state: optimizer.snapshot(),
+            }
+            .save(path)?;
+        }
+        Ok(())
+    }
+
+    /// Report the final result and write the SVG, PNG and genome outputs
+    pub fn save_outputs<S: Shape>(
+        &self,
+        optimizer: &dyn Optimizer<S>,
+        reason: StopReason,
+    ) -> Result<()> {
+        let (shapes, target) = (optimizer.best(), optimizer.target());
+        let (width, height) = (target.reference.width, target.reference.height);
+        let error = optimizer.best_fitness();
+        let mut fb = self.framebuffer(width, height);
+        fb.draw_shapes(shapes);
+
+        if !self.quiet {
+            println!(
+                "Stopped after {} generations: {reason}",
+                optimizer.generation()
+            );
+            println!(
+                "Final best fitness: {:.4} ({} metric, PSNR {:.2} dB)",
+                target.normalise(error),
+                self.metric,
+                target.psnr(&fb)
+            );
+            println!("Saving SVG to: {}", self.output);
+            println!("Saving PNG to: {}", self.output_png);
+            if let Some(path) = &self.output_genome {
+                println!("Saving genome to: {path}");
+            }
+        }
+
+        save_svg(&self.output, shapes, width, height)?;
+        fb.save_png(&self.output_png)?;
+        if let Some(path) = &self.output_genome {
+            let mut genome = Genome::new(width, height, shapes.to_vec());
+            genome.metric = Some(self.metric);
+            genome.seed = Some(self.seed);
+            genome.fitness = Some(target.normalise(error));
+            genome.save(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Primitive};
+    use clap::Parser;
+    use std::path::PathBuf;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        common: CommonArgs,
+    }
+
+    fn parse(args: &[&str]) -> std::result::Result<CommonArgs, clap::Error> {
+        let args = std::iter::once("shapeme").chain(args.iter().copied());
+        Cli::try_parse_from(args).map(|cli| cli.common)
+    }
+
+    // A file name of its own in the temporary directory
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shapeme-cli-{}-{name}", std::process::id()))
+    }
+
+    fn reference() -> FrameBuffer {
+        let mut reference = FrameBuffer::new(16, 8);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            px.fill(if i % 16 < 8 { 0 } else { 255 });
+        }
+        reference
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 90s "), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        for bad in ["", "5d", "m", "abc", "-3", "inf"] {
+            assert!(parse_duration(bad).is_err(), "{bad}");
+        }
+        let error = parse(&["in.png", "--time-limit", "5d"]).err().unwrap();
+        assert!(error.to_string().contains("unknown unit 'd'"), "{error}");
+    }
+
+    #[test]
+    fn metrics_and_shapes_are_parsed_by_name() {
+        let args = parse(&["in.png"]).unwrap();
+        assert_eq!(args.metric, MetricKind::Euclidean);
+        assert_eq!(args.shape, ShapeKind::Triangle);
+
+        let args = parse(&[
+            "in.png",
+            "--metric",
+            "ciede2000",
+            "--shape",
+            "rotated-rectangle",
+        ]);
+        let args = args.unwrap();
+        assert_eq!(args.metric, MetricKind::Ciede2000);
+        assert_eq!(args.shape, ShapeKind::RotatedRectangle);
+
+        let error = parse(&["in.png", "--metric", "psnr"]).err().unwrap();
+        assert!(
+            error.to_string().contains("unknown metric 'psnr'"),
+            "{error}"
+        );
+        assert!(parse(&["in.png", "--shape", "hexagon"]).is_err());
+    }
+
+    #[test]
+    fn the_input_is_required_unless_resuming() {
+        assert!(parse(&[]).is_err());
+        assert_eq!(parse(&["--resume", "run.json"]).unwrap().input, "");
+    }
+
+    #[test]
+    fn masks_are_loaded_from_edges_or_images() {
+        let reference = reference();
+        let auto = load_mask("auto", &reference).unwrap();
+        assert!(auto.weight(8) > auto.weight(0));
+
+        // white on the right, resized to the reference
+        let path = temp_path("mask.png");
+        image::GrayImage::from_fn(4, 2, |x, _| image::Luma([if x < 2 { 0 } else { 255 }]))
+            .save(&path)
+            .unwrap();
+        let mask = load_mask(path.to_str().unwrap(), &reference);
+        std::fs::remove_file(&path).unwrap();
+        let mask = mask.unwrap();
+        assert!(mask.weight(0) < 0.1);
+        assert!((mask.weight(15) - 2.0).abs() < 0.1);
+        assert!(mask.weight(16 * 8 - 1) > 1.5);
+
+        assert!(load_mask(temp_path("missing.png").to_str().unwrap(), &reference).is_err());
+    }
+
+    #[test]
+    fn init_shapes_must_fit_the_image() {
+        let path = temp_path("init.json");
+        let circle = Primitive::from_geometry(
+            ShapeKind::Circle,
+            &[4.0, 4.0, 2.0],
+            Colour::new(1, 2, 3, 50),
+        )
+        .unwrap();
+        Genome::new(16, 8, vec![circle]).save(&path).unwrap();
+        let args = parse(&["in.png", "--init", path.to_str().unwrap()]).unwrap();
+        let fitting = args.load_init::<Primitive>(16, 8);
+        let other = args.load_init::<Primitive>(8, 16);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(fitting.unwrap().unwrap().len(), 1);
+        let error = other.err().unwrap();
+        assert!(error.to_string().contains("is 16x8"), "{error}");
+
+        assert!(
+            parse(&["in.png"])
+                .unwrap()
+                .load_init::<Primitive>(16, 8)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::primitive::{Footprint, in_range, to_u16};
+use crate::{Colour, FrameBuffer, Rect, Shape, ShapeKind, jitter, rand_between};
+use rand_core::RngCore;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Axis-aligned ellipse
+#[derive(Clone)]
+pub struct Ellipse {
+    centre: (u16, u16),
+    radii: (u16, u16),
+    colour: Colour,
+}
+
+impl fmt::Display for Ellipse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.centre;
+        let (rx, ry) = self.radii;
+        write!(f, "Ellipse ({x},{y}) r=({rx},{ry}) {}", self.colour)
+    }
+}
+
+impl Ellipse {
+    pub(crate) fn footprint(&self) -> Footprint {
+        Footprint {
+            centre: (self.centre.0 as f32, self.centre.1 as f32),
+            half_size: (self.radii.0 as f32, self.radii.1 as f32),
+        }
+    }
+
+    pub(crate) fn from_footprint(fp: &Footprint, colour: Colour) -> Self {
+        Ellipse {
+            centre: (to_u16(fp.centre.0), to_u16(fp.centre.1)),
+            radii: (to_u16(fp.half_size.0), to_u16(fp.half_size.1)),
+            colour,
+        }
+    }
+}
+
+impl Shape for Ellipse {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+        let x = rand_between(rng, 0, width as i64 - 1) as u16;
+        let y = rand_between(rng, 0, height as i64 - 1) as u16;
+        let rx = rand_between(rng, 1, (width / 4).max(1) as i64) as u16;
+        let ry = rand_between(rng, 1, (height / 4).max(1) as i64) as u16;
+        let mut e = Ellipse {
+            centre: (x, y),
+            radii: (rx, ry),
+            colour: Colour::random(rng),
+        };
+        e.normalise(width, height);
+        e
+    }
+
+    // mutate: move the centre and stretch either radius
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    ) {
+        self.centre.0 = jitter(rng, self.centre.0, delta, 0, width as i64 - 1);
+        self.centre.1 = jitter(rng, self.centre.1, delta, 0, height as i64 - 1);
+        self.radii.0 = jitter(rng, self.radii.0, delta, 1, width as i64);
+        self.radii.1 = jitter(rng, self.radii.1, delta, 1, height as i64);
+        self.normalise(width, height);
+    }
+
+    // keep the centre on the canvas and both radii non-zero
+    fn normalise(&mut self, width: u16, height: u16) {
+        self.centre.0 = self.centre.0.min(width.saturating_sub(1));
+        self.centre.1 = self.centre.1.min(height.saturating_sub(1));
+        self.radii.0 = self.radii.0.clamp(1, width.max(1));
+        self.radii.1 = self.radii.1.clamp(1, height.max(1));
+    }
+
+    fn colour(&self) -> &Colour {
+        &self.colour
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        &mut self.colour
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        let (x, y) = self.centre;
+        let (rx, ry) = self.radii;
+        fb.fill_ellipse(x as f32, y as f32, rx as f32, ry as f32, &self.colour);
+    }
+
+    fn bounds(&self) -> Rect {
+        let (x, y) = (self.centre.0 as f32, self.centre.1 as f32);
+        let (rx, ry) = (self.radii.0 as f32, self.radii.1 as f32);
+        Rect::around(x - rx, y - ry, x + rx, y + ry)
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (x, y) = self.centre;
+        let (rx, ry) = self.radii;
+        writeln!(
+            w,
+            r#"<ellipse cx="{x}" cy="{y}" rx="{rx}" ry="{ry}" style="{}"/>"#,
+            self.colour.svg_style()
+        )
+    }
+
+    fn kind(&self) -> ShapeKind {
+        ShapeKind::Ellipse
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        let (x, y) = self.centre;
+        let (rx, ry) = self.radii;
+        [x, y, rx, ry].map(f32::from).to_vec()
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        match (kind, geometry) {
+            (ShapeKind::Ellipse, &[x, y, rx, ry])
+                if in_range(geometry) && rx >= 1.0 && ry >= 1.0 =>
+            {
+                Some(Ellipse {
+                    centre: (to_u16(x), to_u16(y)),
+                    radii: (to_u16(rx), to_u16(ry)),
+                    colour,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_round_trips() {
+        let geometry = [12.0, 7.0, 4.0, 9.0];
+        let e = Ellipse::from_geometry(ShapeKind::Ellipse, &geometry, Colour::new(9, 8, 7, 60))
+            .unwrap();
+        assert_eq!(e.geometry(), geometry.to_vec());
+        let copy = Ellipse::from_geometry(e.kind(), &e.geometry(), e.colour().clone()).unwrap();
+        assert_eq!(copy.geometry(), geometry.to_vec());
+    }
+
+    #[test]
+    fn rejects_bad_geometry() {
+        let colour = Colour::new(0, 0, 0, 50);
+        for geometry in [
+            &[1.0, 2.0, 3.0][..],
+            &[1.0, 2.0, 3.0, 0.0],
+            &[1.0, -2.0, 3.0, 4.0],
+            &[1.0, 2.0, f32::INFINITY, 4.0],
+        ] {
+            assert!(Ellipse::from_geometry(ShapeKind::Ellipse, geometry, colour.clone()).is_none());
+        }
+    }
+}
@@ -0,0 +1,668 @@
+use crate::{Colour, FrameBuffer, Mask, Rect, Shape};
+
+/// A measure of how far a rendering is from the reference image - lower is better.
+///
+/// Errors are additive: the error of a region is the sum of the errors of the
+/// pixels (or, for window based metrics, the windows) in it. This is what
+/// allows a mutation to be scored by re-evaluating only the area it changed.
+///
+/// With a `mask`, each pixel's (or window's) error is scaled by its weight.
+pub trait Fitness: Send + Sync {
+    /// Total error of `image` against `reference` over `region`
+    fn error(
+        &self,
+        image: &FrameBuffer,
+        reference: &FrameBuffer,
+        region: Rect,
+        mask: Option<&Mask>,
+    ) -> f64;
+
+    /// Grow `region` so that it consists of whole units (e.g. windows) of the metric
+    fn align(&self, region: Rect) -> Rect {
+        region
+    }
+}
+
+/// Sum of squared RGB differences, averaged over the three channels.
+/// Per pixel this is the mean squared error (MSE).
+pub struct SumOfSquares;
+
+impl Fitness for SumOfSquares {
+    fn error(
+        &self,
+        image: &FrameBuffer,
+        reference: &FrameBuffer,
+        region: Rect,
+        mask: Option<&Mask>,
+    ) -> f64 {
+        let mut d = 0.0;
+        for_each_row(image, reference, region, |start, a, b| {
+            for (i, (pa, pb)) in a.chunks(3).zip(b.chunks(3)).enumerate() {
+                let mut sq: i64 = 0;
+                for (x, y) in pa.iter().zip(pb) {
+                    let dv = *x as i64 - *y as i64;
+                    sq += dv * dv;
+                }
+                d += weighted(sq as f64, mask, start + i);
+            }
+        });
+        d / 3.0
+    }
+}
+
+/// Per-pixel Euclidean RGB distance, truncated to an integer - the original
+/// metric, see `FrameBuffer::diff`.
+pub struct Euclidean;
+
+impl Fitness for Euclidean {
+    fn error(
+        &self,
+        image: &FrameBuffer,
+        reference: &FrameBuffer,
+        region: Rect,
+        mask: Option<&Mask>,
+    ) -> f64 {
+        let Some(mask) = mask else {
+            return image.diff_region(reference, region) as f64;
+        };
+        let mut d = 0.0;
+        for_each_row(image, reference, region, |start, a, b| {
+            for (i, (pa, pb)) in a.chunks(3).zip(b.chunks(3)).enumerate() {
+                let dr = pa[0] as i64 - pb[0] as i64;
+                let dg = pa[1] as i64 - pb[1] as i64;
+                let db = pa[2] as i64 - pb[2] as i64;
+                let dist = ((dr * dr + dg * dg + db * db) as f64).sqrt() as i64;
+                d += dist as f64 * mask.weight(start + i);
+            }
+        });
+        d
+    }
+}
+
+const SSIM_BLOCK: usize = 8;
+
+/// Structural dissimilarity, `1 - SSIM`, computed per colour channel over a
+/// fixed grid of 8x8 blocks. Each block counts once per pixel it contains.
+pub struct Ssim;
+
+impl Ssim {
+    // 1 - SSIM of one block, averaged over the colour channels
+    fn block(image: &FrameBuffer, reference: &FrameBuffer, block: Rect) -> f64 {
+        const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+        const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+        let w = image.width as usize;
+        let n = (block.width() * block.height()) as f64;
+        let mut dssim = 0.0;
+        for c in 0..3 {
+            let (mut sx, mut sy, mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in block.y0..block.y1 {
+                for x in block.x0..block.x1 {
+                    let i = (y * w + x) * 3 + c;
+                    let (a, b) = (image.pixels[i] as f64, reference.pixels[i] as f64);
+                    sx += a;
+                    sy += b;
+                    sxx += a * a;
+                    syy += b * b;
+                    sxy += a * b;
+                }
+            }
+            let (mx, my) = (sx / n, sy / n);
+            let vx = sxx / n - mx * mx;
+            let vy = syy / n - my * my;
+            let cov = sxy / n - mx * my;
+            let ssim = ((2.0 * mx * my + C1) * (2.0 * cov + C2))
+                / ((mx * mx + my * my + C1) * (vx + vy + C2));
+            dssim += 1.0 - ssim;
+        }
+        dssim / 3.0
+    }
+}
+
+impl Fitness for Ssim {
+    fn error(
+        &self,
+        image: &FrameBuffer,
+        reference: &FrameBuffer,
+        region: Rect,
+        mask: Option<&Mask>,
+    ) -> f64 {
+        let bounds = image.bounds();
+        let region = self.align(region).intersect(&bounds);
+        let mut d = 0.0;
+        for y0 in (region.y0..region.y1).step_by(SSIM_BLOCK) {
+            for x0 in (region.x0..region.x1).step_by(SSIM_BLOCK) {
+                let block = Rect::new(x0, y0, x0 + SSIM_BLOCK, y0 + SSIM_BLOCK).intersect(&bounds);
+                if !block.is_empty() {
+                    let area = block.width() * block.height();
+                    let weight = match mask {
+                        Some(mask) => {
+                            let w = image.width as usize;
+                            (block.y0..block.y1)
+                                .flat_map(|y| (block.x0..block.x1).map(move |x| y * w + x))
+                                .map(|i| mask.weight(i))
+                                .sum::<f64>()
+                        }
+                        None => area as f64,
+                    };
+                    d += Self::block(image, reference, block) * weight;
+                }
+            }
+        }
+        d
+    }
+
+    fn align(&self, region: Rect) -> Rect {
+        Rect::new(
+            region.x0 / SSIM_BLOCK * SSIM_BLOCK,
+            region.y0 / SSIM_BLOCK * SSIM_BLOCK,
+            region.x1.div_ceil(SSIM_BLOCK) * SSIM_BLOCK,
+            region.y1.div_ceil(SSIM_BLOCK) * SSIM_BLOCK,
+        )
+    }
+}
+
+/// Perceptual colour difference: CIEDE2000 ΔE between the pixels in CIELAB space.
+pub struct Ciede2000 {
+    // sRGB component to linear light
+    linear: [f32; 256],
+    // CIELAB of each reference pixel, converted once
+    reference: Vec<[f32; 3]>,
+}
+
+impl Ciede2000 {
+    pub fn new(reference: &FrameBuffer) -> Self {
+        let linear = std::array::from_fn(|v| {
+            let c = v as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        let mut metric = Self {
+            linear,
+            reference: Vec::new(),
+        };
+        metric.reference = reference
+            .pixels
+            .chunks(3)
+            .map(|p| metric.lab(p[0], p[1], p[2]))
+            .collect();
+        metric
+    }
+
+    // sRGB to CIELAB, D65 white point
+    fn lab(&self, r: u8, g: u8, b: u8) -> [f32; 3] {
+        let (r, g, b) = (
+            self.linear[r as usize],
+            self.linear[g as usize],
+            self.linear[b as usize],
+        );
+        let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+        fn f(t: f32) -> f32 {
+            const DELTA: f32 = 6.0 / 29.0;
+            if t > DELTA * DELTA * DELTA {
+                t.cbrt()
+            } else {
+                t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+            }
+        }
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+    }
+
+    /// CIEDE2000 colour difference of two CIELAB colours
+    pub fn delta_e(lab1: [f32; 3], lab2: [f32; 3]) -> f64 {
+        let [l1, a1, b1] = lab1.map(|v| v as f64);
+        let [l2, a2, b2] = lab2.map(|v| v as f64);
+        let pow25_7 = 25f64.powi(7);
+
+        let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+        let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+        let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+        let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+        let hue = |b: f64, a: f64| {
+            if a == 0.0 && b == 0.0 {
+                0.0
+            } else {
+                b.atan2(a).to_degrees().rem_euclid(360.0)
+            }
+        };
+        let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+        let dl = l2 - l1;
+        let dc = c2 - c1;
+        let dh = if c1 * c2 == 0.0 {
+            0.0
+        } else if (h2 - h1).abs() <= 180.0 {
+            h2 - h1
+        } else if h2 - h1 > 180.0 {
+            h2 - h1 - 360.0
+        } else {
+            h2 - h1 + 360.0
+        };
+        let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+        let l_bar = (l1 + l2) / 2.0;
+        let c_bar = (c1 + c2) / 2.0;
+        let h_bar = if c1 * c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+
+        let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+            + 0.24 * (2.0 * h_bar).to_radians().cos()
+            + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+            - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+        let d_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+        let rc = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt();
+        let sl = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+        let sc = 1.0 + 0.045 * c_bar;
+        let sh = 1.0 + 0.015 * c_bar * t;
+        let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+        let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+        (l * l + c * c + h * h + rt * c * h).max(0.0).sqrt()
+    }
+}
+
+impl Fitness for Ciede2000 {
+    fn error(
+        &self,
+        image: &FrameBuffer,
+        _reference: &FrameBuffer,
+        region: Rect,
+        mask: Option<&Mask>,
+    ) -> f64 {
+        let region = region.intersect(&image.bounds());
+        let w = image.width as usize;
+        let mut d = 0.0;
+        for y in region.y0..region.y1 {
+            for x in region.x0..region.x1 {
+                let i = y * w + x;
+                let p = &image.pixels[i * 3..i * 3 + 3];
+                let de = Self::delta_e(self.lab(p[0], p[1], p[2]), self.reference[i]);
+                d += weighted(de, mask, i);
+            }
+        }
+        d
+    }
+}
+
+// call `f` with the index of the first pixel of each row of `region`, and
+// the matching RGB rows of `a` and `b`
+fn for_each_row<F: FnMut(usize, &[u8], &[u8])>(
+    a: &FrameBuffer,
+    b: &FrameBuffer,
+    region: Rect,
+    mut f: F,
+) {
+    let region = region.intersect(&a.bounds());
+    if region.is_empty() {
+        return;
+    }
+    let w = a.width as usize;
+    for y in region.y0..region.y1 {
+        let start = y * w + region.x0;
+        let row = start * 3..(y * w + region.x1) * 3;
+        f(start, &a.pixels[row.clone()], &b.pixels[row]);
+    }
+}
+
+// `error` of pixel `idx` scaled by its mask weight
+fn weighted(error: f64, mask: Option<&Mask>, idx: usize) -> f64 {
+    match mask {
+        Some(mask) => error * mask.weight(idx),
+        None => error,
+    }
+}
+
+/// The metrics available on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    Mse,
+    Euclidean,
+    Ssim,
+    Ciede2000,
+}
+
+named_enum!(MetricKind, "metric", {
+    Mse => "mse",
+    Euclidean => "euclidean",
+    Ssim => "ssim",
+    Ciede2000 => "ciede2000",
+});
+
+impl MetricKind {
+    pub fn build(self, reference: &FrameBuffer) -> Box<dyn Fitness> {
+        match self {
+            MetricKind::Mse => Box::new(SumOfSquares),
+            MetricKind::Euclidean => Box::new(Euclidean),
+            MetricKind::Ssim => Box::new(Ssim),
+            MetricKind::Ciede2000 => Box::new(Ciede2000::new(reference)),
+        }
+    }
+}
+
+/// The image being approximated together with the metric (and optional
+/// importance mask) used to score approximations of it.
+pub struct Target {
+    pub reference: FrameBuffer,
+    pub metric: Box<dyn Fitness>,
+    pub mask: Option<Mask>,
+}
+
+impl Target {
+    pub fn new(reference: FrameBuffer, metric: MetricKind) -> Self {
+        let metric = metric.build(&reference);
+        Self {
+            reference,
+            metric,
+            mask: None,
+        }
+    }
+
+    /// Weight the error of each pixel by `mask`
+    pub fn with_mask(mut self, mask: Option<Mask>) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Total error of `image` over the whole image
+    pub fn error(&self, image: &FrameBuffer) -> f64 {
+        self.error_region(image, self.reference.bounds())
+    }
+
+    /// Total error of `image` over `region` (grown to whole units of the metric)
+    pub fn error_region(&self, image: &FrameBuffer, region: Rect) -> f64 {
+        self.metric
+            .error(image, &self.reference, region, self.mask.as_ref())
+    }
+
+    /// A total error as the mean error per pixel, comparable across image sizes
+    pub fn normalise(&self, error: f64) -> f64 {
+        error / (self.reference.width as f64 * self.reference.height as f64)
+    }
+
+    /// Give `shape` the colour that, drawn over `canvas` with the shape's
+    /// opacity, best matches the reference in squared error (weighted by the
+    /// mask), whatever the metric. `canvas` holds what lies beneath the
+    /// shape and is left as it was.
+    pub fn solve_colour<S: Shape>(&self, shape: &mut S, canvas: &mut FrameBuffer) {
+        let region = shape.bounds().intersect(&canvas.bounds());
+        if region.is_empty() {
+            return;
+        }
+        // Each channel of a pixel the shape covers becomes
+        // `under + colour * k`, where `k` is the opacity times the coverage.
+        // Drawn in black the shape leaves `under`, drawn in white `under +
+        // 255 * k`; the best colour then follows by least squares.
+        let [r, g, b, alpha] = shape.colour().rgba();
+        let saved = canvas.copy_region(region);
+        let w = canvas.width as usize;
+        let mut draw = |[r, g, b]: [u8; 3]| {
+            *shape.colour_mut() = Colour::new(r, g, b, alpha);
+            shape.draw(canvas);
+            let drawn = canvas.copy_region(region);
+            canvas.paste_region(region, &saved);
+            drawn
+        };
+        let (black, white) = (draw([0; 3]), draw([255; 3]));
+
+        // the mask weight and reference pixel of each pixel of `region`
+        let pixels = || {
+            (region.y0..region.y1).flat_map(move |y| {
+                (region.x0..region.x1).map(move |x| {
+                    let p = y * w + x;
+                    let weight = self.mask.as_ref().map_or(1.0, |m| m.weight(p));
+                    (weight, &self.reference.pixels[p * 3..p * 3 + 3])
+                })
+            })
+        };
+        let (mut num, mut den) = ([0.0f64; 3], [0.0f64; 3]);
+        for (i, (weight, reference)) in pixels().enumerate() {
+            for c in 0..3 {
+                let k = (white[i * 3 + c] as f64 - black[i * 3 + c] as f64) / 255.0;
+                let residual = reference[c] as f64 - black[i * 3 + c] as f64;
+                num[c] += weight * k * residual;
+                den[c] += weight * k * k;
+            }
+        }
+        let solve = |c: usize, old: u8| {
+            if den[c] > 0.0 {
+                (num[c] / den[c]).round().clamp(0.0, 255.0) as u8
+            } else {
+                old
+            }
+        };
+        let mut rgb = [solve(0, r), solve(1, g), solve(2, b)];
+
+        // Blending rounds down, which the least squares solution leaves out,
+        // so it can be a step off. The channels blend independently: each is
+        // moved by one while that lowers its own squared error.
+        let errors = |drawn: &[u8]| {
+            let mut errors = [0.0f64; 3];
+            for (i, (weight, reference)) in pixels().enumerate() {
+                for c in 0..3 {
+                    let d = drawn[i * 3 + c] as f64 - reference[c] as f64;
+                    errors[c] += weight * d * d;
+                }
+            }
+            errors
+        };
+        let mut best = errors(&draw(rgb));
+        loop {
+            let mut moved = false;
+            for step in [-1, 1] {
+                let candidate = rgb.map(|v| v.saturating_add_signed(step));
+                let candidate_errors = errors(&draw(candidate));
+                for c in 0..3 {
+                    if candidate_errors[c] < best[c] {
+                        rgb[c] = candidate[c];
+                        best[c] = candidate_errors[c];
+                        moved = true;
+                    }
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+        *shape.colour_mut() = Colour::new(rgb[0], rgb[1], rgb[2], alpha);
+    }
+
+    /// Peak signal-to-noise ratio of `image` in dB - higher is better. The
+    /// mask is ignored.
+    pub fn psnr(&self, image: &FrameBuffer) -> f64 {
+        let bounds = self.reference.bounds();
+        let mse = self.normalise(SumOfSquares.error(image, &self.reference, bounds, None));
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sharma, Wu and Dalal, "The CIEDE2000 color-difference formula:
+    // implementation notes, supplementary test data, and mathematical
+    // observations" (2005), table 1
+    const SHARMA: [([f32; 3], [f32; 3], f64); 34] = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+        ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+        ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -1.1848, -84.8006], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -0.9009, -85.5211], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0009], 7.1792),
+        ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0010], 7.1792),
+        ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0011], 7.2195),
+        ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0012], 7.2195),
+        ([50.0, -0.0010, 2.4900], [50.0, 0.0009, -2.4900], 4.8045),
+        ([50.0, -0.0010, 2.4900], [50.0, 0.0010, -2.4900], 4.8045),
+        ([50.0, -0.0010, 2.4900], [50.0, 0.0011, -2.4900], 4.7461),
+        ([50.0, 2.5000, 0.0000], [50.0, 0.0000, -2.5000], 4.3065),
+        ([50.0, 2.5000, 0.0000], [73.0, 25.0000, -18.0000], 27.1492),
+        ([50.0, 2.5000, 0.0000], [61.0, -5.0000, 29.0000], 22.8977),
+        ([50.0, 2.5000, 0.0000], [56.0, -27.0000, -3.0000], 31.9030),
+        ([50.0, 2.5000, 0.0000], [58.0, 24.0000, 15.0000], 19.4535),
+        ([50.0, 2.5000, 0.0000], [50.0, 3.1736, 0.5854], 1.0000),
+        ([50.0, 2.5000, 0.0000], [50.0, 3.2972, 0.0000], 1.0000),
+        ([50.0, 2.5000, 0.0000], [50.0, 1.8634, 0.5757], 1.0000),
+        ([50.0, 2.5000, 0.0000], [50.0, 3.2592, 0.3350], 1.0000),
+        (
+            [60.2574, -34.0099, 36.2677],
+            [60.4626, -34.1751, 39.4387],
+            1.2644,
+        ),
+        (
+            [63.0109, -31.0961, -5.8663],
+            [62.8187, -29.7946, -4.0864],
+            1.2630,
+        ),
+        (
+            [61.2901, 3.7196, -5.3901],
+            [61.4292, 2.2480, -4.9620],
+            1.8731,
+        ),
+        (
+            [35.0831, -44.1164, 3.7933],
+            [35.0232, -40.0716, 1.5901],
+            1.8645,
+        ),
+        (
+            [22.7233, 20.0904, -46.6940],
+            [23.0331, 14.9730, -42.5619],
+            2.0373,
+        ),
+        (
+            [36.4612, 47.8580, 18.3852],
+            [36.2715, 50.5065, 21.2231],
+            1.4146,
+        ),
+        (
+            [90.8027, -2.0831, 1.4410],
+            [91.1528, -1.6435, 0.0447],
+            1.4441,
+        ),
+        (
+            [90.9257, -0.5406, -0.9208],
+            [88.6381, -0.8985, -0.7239],
+            1.5381,
+        ),
+        (
+            [6.7747, -0.2908, -2.4247],
+            [5.8714, -0.0985, -2.2286],
+            0.6377,
+        ),
+        (
+            [2.0776, 0.0795, -1.1350],
+            [0.9033, -0.0636, -0.5514],
+            0.9082,
+        ),
+    ];
+
+    fn image(seed: u8) -> FrameBuffer {
+        let mut fb = FrameBuffer::new(20, 12);
+        for (i, px) in fb.pixels.iter_mut().enumerate() {
+            *px = (i as u8).wrapping_mul(37).wrapping_add(seed);
+        }
+        fb
+    }
+
+    #[test]
+    fn ciede2000_matches_sharma_reference_data() {
+        for (i, (lab1, lab2, expected)) in SHARMA.into_iter().enumerate() {
+            let de = Ciede2000::delta_e(lab1, lab2);
+            assert!(
+                (de - expected).abs() < 1e-4,
+                "pair {}: {de} != {expected}",
+                i + 1
+            );
+            assert!((Ciede2000::delta_e(lab2, lab1) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn identical_images_have_no_error() {
+        for metric in MetricKind::ALL {
+            let target = Target::new(image(0), metric);
+            assert_eq!(target.error(&image(0)), 0.0, "{metric}");
+        }
+    }
+
+    #[test]
+    fn ssim_of_identical_images_is_one() {
+        let (a, b) = (image(0), image(90));
+        let bounds = a.bounds();
+        for y in (0..12).step_by(SSIM_BLOCK) {
+            for x in (0..20).step_by(SSIM_BLOCK) {
+                let block = Rect::new(x, y, x + SSIM_BLOCK, y + SSIM_BLOCK).intersect(&bounds);
+                assert!(Ssim::block(&a, &a, block).abs() < 1e-12);
+                assert!(Ssim::block(&a, &b, block) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn different_images_have_positive_error() {
+        for metric in MetricKind::ALL {
+            let target = Target::new(image(0), metric);
+            assert!(target.error(&image(90)) > 0.0, "{metric}");
+        }
+    }
+
+    #[test]
+    fn solved_colour_beats_its_neighbours() {
+        use crate::{Primitive, ShapeKind};
+
+        let shapes = [
+            (ShapeKind::Rectangle, &[3.0, 2.0, 15.0, 9.0][..]),
+            (ShapeKind::Circle, &[10.0, 6.0, 5.0]),
+            (ShapeKind::Triangle, &[1.0, 1.0, 18.5, 4.0, 6.25, 11.0]),
+        ];
+        for antialias in [false, true] {
+            for (kind, geometry) in shapes {
+                let target = Target::new(image(0), MetricKind::Mse);
+                let mut canvas = image(90);
+                canvas.antialias = antialias;
+                let mut shape =
+                    Primitive::from_geometry(kind, geometry, Colour::new(0, 0, 0, 60)).unwrap();
+                target.solve_colour(&mut shape, &mut canvas);
+                assert_eq!(canvas.pixels, image(90).pixels, "canvas left as it was");
+
+                let error = |rgba: [u8; 4]| {
+                    let mut shape = shape.clone();
+                    *shape.colour_mut() = Colour::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+                    let mut fb = image(90);
+                    fb.antialias = antialias;
+                    shape.draw(&mut fb);
+                    target.error(&fb)
+                };
+                let solved = shape.colour().rgba();
+                let best = error(solved);
+                for c in 0..3 {
+                    for step in [-1, 1] {
+                        let mut rgba = solved;
+                        rgba[c] = (rgba[c] as i32 + step).clamp(0, 255) as u8;
+                        assert!(
+                            best <= error(rgba),
+                            "{kind} {antialias}: {solved:?} worse than {rgba:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,77 @@
-use crate::Triangle;
+use crate::{Colour, Shape, Target, Triangle};
+
+/// Half-open pixel rectangle `[x0, x1) x [y0, y1)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Rect {
+    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    /// The whole pixels touched by the extent `[min_x, max_x] x [min_y, max_y]`,
+    /// with a one pixel margin so that rounding in the rasterisers never
+    /// escapes it. Negative coordinates saturate to 0.
+    pub fn around(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Self {
+        Self {
+            x0: (min_x.floor() - 1.0) as usize,
+            y0: (min_y.floor() - 1.0) as usize,
+            x1: (max_x.ceil() + 2.0) as usize,
+            y1: (max_y.ceil() + 2.0) as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    /// Smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    /// Overlap of the two, possibly empty
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+}
 
 pub struct FrameBuffer {
     pub pixels: Vec<u8>, // RGB, 3 bytes per pixel
     pub width: u16,
     pub height: u16,
+    /// Rasterise triangles with coverage-based anti-aliasing
+    pub antialias: bool,
+    /// Pixels per unit of shape coordinates, for rendering a solution at a
+    /// different resolution than it was made for
+    pub scale: f32,
+    /// RGB colour the framebuffer is cleared to
+    pub background: [u8; 3],
+    // drawing outside this rectangle is suppressed, see redraw_region
+    clip: Rect,
 }
 
 impl FrameBuffer {
@@ -12,20 +80,40 @@ impl FrameBuffer {
             pixels: vec![0; width as usize * height as usize * 3],
             width,
             height,
+            antialias: false,
+            scale: 1.0,
+            background: [0; 3],
+            clip: Rect::new(0, 0, width as usize, height as usize),
         }
     }
 
+    /// The rectangle covering the whole framebuffer
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width as usize, self.height as usize)
+    }
+
     pub fn clear(&mut self) {
-        self.pixels.fill(0);
+        if self.background == [0; 3] {
+            self.pixels.fill(0);
+        } else {
+            for px in self.pixels.chunks_exact_mut(3) {
+                px.copy_from_slice(&self.background);
+            }
+        }
     }
 
     /// Load from an image crate DynamicImage
     pub fn from_image(img: &image::DynamicImage) -> Self {
         let rgb = img.to_rgb8();
+        let (rgb_width, rgb_height) = (rgb.width() as usize, rgb.height() as usize);
         Self {
             width: rgb.width() as u16,
             height: rgb.height() as u16,
             pixels: rgb.into_raw(),
+            antialias: false,
+            scale: 1.0,
+            background: [0; 3],
+            clip: Rect::new(0, 0, rgb_width, rgb_height),
         }
     }
 
@@ -47,40 +135,44 @@ impl FrameBuffer {
     /// new_pixel = old_pixel * (1 - alpha) + colour * alpha
     /// ```
     ///
-    /// Lines outside the framebuffer bounds are clipped or ignored entirely.
-    fn draw_hline(&mut self, x1: f32, x2: f32, y: f32, r: u8, g: u8, b: u8, alpha: f32) {
+    /// Lines outside the framebuffer bounds (or the current clip rectangle)
+    /// are clipped or ignored entirely.
+    fn draw_hline(&mut self, x1: f32, x2: f32, y: f32, colour: &Colour) {
         let y = y as i32;
-        if y < 0 || y >= self.height as i32 {
+        if y < self.clip.y0 as i32 || y >= self.clip.y1 as i32 {
             return;
         }
 
         let mut x_start = x1.min(x2) as i32;
         let mut x_end = x1.max(x2) as i32;
 
-        x_start = x_start.max(0);
-        x_end = x_end.min(self.width as i32 - 1);
-
-        let y = y as usize;
-        let one_minus_alpha = 1.0 - alpha;
+        x_start = x_start.max(self.clip.x0 as i32);
+        x_end = x_end.min(self.clip.x1 as i32 - 1);
 
+        let alpha = colour.opacity();
         for x in x_start..=x_end {
-            let idx = (y * self.width as usize + x as usize) * 3;
-            self.pixels[idx] = (self.pixels[idx] as f32 * one_minus_alpha + r as f32 * alpha) as u8;
-            self.pixels[idx + 1] =
-                (self.pixels[idx + 1] as f32 * one_minus_alpha + g as f32 * alpha) as u8;
-            self.pixels[idx + 2] =
-                (self.pixels[idx + 2] as f32 * one_minus_alpha + b as f32 * alpha) as u8;
+            self.blend_pixel(x as usize, y as usize, colour, alpha);
         }
     }
 
-    pub fn draw_triangle(&mut self, t: &Triangle) {
-        let [(x1, y1), (x2, y2), (x3, y3)] = t.vertices;
+    /// Blends `colour` into pixel (`x`, `y`) with the given opacity.
+    fn blend_pixel(&mut self, x: usize, y: usize, colour: &Colour, alpha: f32) {
+        let idx = (y * self.width as usize + x) * 3;
+        let one_minus_alpha = 1.0 - alpha;
+        for (c, v) in [colour.r, colour.g, colour.b].into_iter().enumerate() {
+            self.pixels[idx + c] =
+                (self.pixels[idx + c] as f32 * one_minus_alpha + v as f32 * alpha) as u8;
+        }
+    }
 
-        let (ax, ay) = (x1 as f32, y1 as f32);
-        let (bx, by) = (x2 as f32, y2 as f32);
-        let (cx, cy) = (x3 as f32, y3 as f32);
+    pub fn draw_triangle(&mut self, t: &Triangle) {
+        if self.antialias {
+            self.draw_triangle_aa(t);
+            return;
+        }
 
-        let alpha = t.colour.alpha as f32 / 100.0;
+        let [(ax, ay), (bx, by), (cx, cy)] =
+            t.vertices.map(|(x, y)| (x * self.scale, y * self.scale));
 
         let dx1 = if by - ay > 0.0 {
             (bx - ax) / (by - ay)
@@ -103,21 +195,21 @@ impl FrameBuffer {
 
         if dx1 > dx2 {
             while sy <= by {
-                self.draw_hline(sx, ex, sy, t.colour.r, t.colour.g, t.colour.b, alpha);
+                self.draw_hline(sx, ex, sy, &t.colour);
                 sy += 1.0;
                 sx += dx2;
                 ex += dx1;
             }
             ex = bx;
             while sy <= cy {
-                self.draw_hline(sx, ex, sy, t.colour.r, t.colour.g, t.colour.b, alpha);
+                self.draw_hline(sx, ex, sy, &t.colour);
                 sy += 1.0;
                 sx += dx2;
                 ex += dx3;
             }
         } else {
             while sy <= by {
-                self.draw_hline(sx, ex, sy, t.colour.r, t.colour.g, t.colour.b, alpha);
+                self.draw_hline(sx, ex, sy, &t.colour);
                 sy += 1.0;
                 sx += dx1;
                 ex += dx2;
@@ -125,7 +217,7 @@ impl FrameBuffer {
             sx = bx;
             sy = by + 1.0;
             while sy <= cy {
-                self.draw_hline(sx, ex, sy, t.colour.r, t.colour.g, t.colour.b, alpha);
+                self.draw_hline(sx, ex, sy, &t.colour);
                 sy += 1.0;
                 sx += dx3;
                 ex += dx2;
@@ -133,23 +225,366 @@ impl FrameBuffer {
         }
     }
 
-    pub fn draw_triangles(&mut self, triangles: &[Triangle]) {
+    /// Draws a triangle with anti-aliased edges, matching how SVG renderers
+    /// draw the polygon written by `save_svg`.
+    ///
+    /// Pixel (x, y) is the unit square with its centre at (x + 0.5, y + 0.5).
+    /// Its coverage is estimated from the signed distance of the centre to
+    /// each edge, each edge contributing `clamp(0.5 + distance, 0, 1)`, and
+    /// the colour is blended with `alpha * coverage`.
+    fn draw_triangle_aa(&mut self, t: &Triangle) {
+        let [a, mut b, mut c] = t.vertices.map(|(x, y)| (x * self.scale, y * self.scale));
+
+        // orient so that the inside is on the positive side of every edge
+        let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+        if area.abs() < 1e-6 {
+            return; // degenerate - covers no area
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+
+        // edge (p, q) as the line nx * x + ny * y + d = 0 with unit normal
+        let edges = [(a, b), (b, c), (c, a)].map(|(p, q)| {
+            let (nx, ny) = (p.1 - q.1, q.0 - p.0);
+            let len = (nx * nx + ny * ny).sqrt();
+            (nx / len, ny / len, -(nx * p.0 + ny * p.1) / len)
+        });
+
+        // limited to the vertex bounding box - sharp corners would otherwise
+        // bleed past the vertices
+        let bbox = Rect::new(
+            a.0.min(b.0).min(c.0).floor() as usize,
+            a.1.min(b.1).min(c.1).floor() as usize,
+            a.0.max(b.0).max(c.0).ceil() as usize + 1,
+            a.1.max(b.1).max(c.1).ceil() as usize + 1,
+        )
+        .intersect(&self.clip);
+
+        let alpha = t.colour.opacity();
+        for y in bbox.y0..bbox.y1 {
+            let py = y as f32 + 0.5;
+            for x in bbox.x0..bbox.x1 {
+                let px = x as f32 + 0.5;
+                let coverage: f32 = edges
+                    .iter()
+                    .map(|(nx, ny, d)| (0.5 + nx * px + ny * py + d).clamp(0.0, 1.0))
+                    .product();
+                if coverage > 0.0 {
+                    self.blend_pixel(x, y, &t.colour, alpha * coverage);
+                }
+            }
+        }
+    }
+
+    /// Fills the axis-aligned ellipse centred on (`cx`, `cy`) with radii `rx`
+    /// and `ry`, one horizontal line per row.
+    pub fn fill_ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32, colour: &Colour) {
+        let [cx, cy, rx, ry] = [cx, cy, rx, ry].map(|v| v * self.scale);
+        if ry <= 0.0 {
+            self.draw_hline(cx - rx, cx + rx, cy, colour);
+            return;
+        }
+        let mut y = (cy - ry).ceil();
+        while y <= cy + ry {
+            let t = (y - cy) / ry;
+            let half = rx * (1.0 - t * t).max(0.0).sqrt();
+            self.draw_hline(cx - half, cx + half, y, colour);
+            y += 1.0;
+        }
+    }
+
+    /// Fills the rectangle with corners (`x1`, `y1`) and (`x2`, `y2`), both inclusive.
+    ///
+    /// When scaled, the rectangle covers every pixel overlapping the scaled
+    /// extent of the pixels between the corners.
+    pub fn fill_rect(&mut self, x1: u16, y1: u16, x2: u16, y2: u16, colour: &Colour) {
+        let span = |a: u16, b: u16| {
+            let first = (a.min(b) as f32 * self.scale).floor();
+            let last = ((a.max(b) as f32 + 1.0) * self.scale).ceil() - 1.0;
+            (first, last.max(first))
+        };
+        let (x_start, x_end) = span(x1, x2);
+        let (y_start, y_end) = span(y1, y2);
+        let mut y = y_start;
+        while y <= y_end {
+            self.draw_hline(x_start, x_end, y, colour);
+            y += 1.0;
+        }
+    }
+
+    /// Fills a convex polygon given by its vertices in drawing order.
+    ///
+    /// Each row is filled between the leftmost and rightmost crossing of the
+    /// polygon outline, so the vertices need not be sorted.
+    pub fn fill_convex_polygon(&mut self, points: &[(f32, f32)], colour: &Colour) {
+        let scaled: Vec<(f32, f32)>;
+        let points = if self.scale == 1.0 {
+            points
+        } else {
+            scaled = points
+                .iter()
+                .map(|&(x, y)| (x * self.scale, y * self.scale))
+                .collect();
+            &scaled
+        };
+        let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+
+        let mut y = min_y.ceil();
+        while y <= max_y {
+            let mut x_min = f32::INFINITY;
+            let mut x_max = f32::NEG_INFINITY;
+            for (i, &(px, py)) in points.iter().enumerate() {
+                let (qx, qy) = points[(i + 1) % points.len()];
+                if y < py.min(qy) || y > py.max(qy) {
+                    continue;
+                }
+                let xs = if py == qy {
+                    [px, qx]
+                } else {
+                    let x = px + (y - py) * (qx - px) / (qy - py);
+                    [x, x]
+                };
+                for x in xs {
+                    x_min = x_min.min(x);
+                    x_max = x_max.max(x);
+                }
+            }
+            if x_min <= x_max {
+                self.draw_hline(x_min, x_max, y, colour);
+            }
+            y += 1.0;
+        }
+    }
+
+    pub fn draw_shapes<S: Shape>(&mut self, shapes: &[S]) {
         self.clear();
-        for t in triangles {
-            self.draw_triangle(t);
+        for s in shapes {
+            s.draw(self);
         }
     }
 
-    pub fn diff(&self, other: &FrameBuffer) -> i64 {
+    /// Clears `region` and redraws the parts of `shapes` that fall inside it,
+    /// leaving the rest of the framebuffer untouched.
+    ///
+    /// The result is pixel-identical to `draw_shapes` within `region`.
+    pub fn redraw_region<S: Shape>(&mut self, shapes: &[S], region: Rect) {
+        self.clear_region(region);
+        self.draw_region(shapes, region);
+    }
+
+    /// Clears the pixels of `region` to the background colour.
+    pub fn clear_region(&mut self, region: Rect) {
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return;
+        }
+        let w = self.width as usize;
+        for y in region.y0..region.y1 {
+            let row = &mut self.pixels[(y * w + region.x0) * 3..(y * w + region.x1) * 3];
+            if self.background == [0; 3] {
+                row.fill(0);
+            } else {
+                for px in row.chunks_exact_mut(3) {
+                    px.copy_from_slice(&self.background);
+                }
+            }
+        }
+    }
+
+    /// Draws the parts of `shapes` that fall inside `region` on top of the
+    /// current contents.
+    pub fn draw_region<S: Shape>(&mut self, shapes: &[S], region: Rect) {
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return;
+        }
+        self.clip = region;
+        for s in shapes {
+            if !s.bounds().intersect(&region).is_empty() {
+                s.draw(self);
+            }
+        }
+        self.clip = self.bounds();
+    }
+
+    /// Copies the pixels of `region` from a framebuffer of the same size.
+    pub fn copy_region_from(&mut self, other: &FrameBuffer, region: Rect) {
         debug_assert_eq!(self.pixels.len(), other.pixels.len());
 
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return;
+        }
+        let w = self.width as usize;
+        for y in region.y0..region.y1 {
+            let row = (y * w + region.x0) * 3..(y * w + region.x1) * 3;
+            self.pixels[row.clone()].copy_from_slice(&other.pixels[row]);
+        }
+    }
+
+    /// Copies out the pixels of `region`, see `paste_region`.
+    pub fn copy_region(&self, region: Rect) -> Vec<u8> {
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return Vec::new();
+        }
+        let w = self.width as usize;
+        let mut saved = Vec::with_capacity(region.width() * region.height() * 3);
+        for y in region.y0..region.y1 {
+            saved.extend_from_slice(&self.pixels[(y * w + region.x0) * 3..(y * w + region.x1) * 3]);
+        }
+        saved
+    }
+
+    /// Restores pixels previously saved with `copy_region` for the same region.
+    pub fn paste_region(&mut self, region: Rect, saved: &[u8]) {
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return;
+        }
+        let w = self.width as usize;
+        let row_len = region.width() * 3;
+        for (y, row) in (region.y0..region.y1).zip(saved.chunks(row_len)) {
+            self.pixels[(y * w + region.x0) * 3..(y * w + region.x1) * 3].copy_from_slice(row);
+        }
+    }
+
+    /// Re-renders `region` from `shapes` and returns the updated total error
+    /// against `target`, given that `error` was the total before the change.
+    /// Only the pixels in `region` are drawn and compared, so when a single
+    /// shape changes this is far cheaper than `draw_shapes` followed by
+    /// `Target::error`.
+    ///
+    /// `region` must cover everything that changed - typically the union of
+    /// the bounds of a shape before and after mutation.
+    pub fn update_error<S: Shape>(
+        &mut self,
+        shapes: &[S],
+        region: Rect,
+        target: &Target,
+        error: f64,
+    ) -> f64 {
+        let before = target.error_region(self, region);
+        self.redraw_region(shapes, region);
+        error - before + target.error_region(self, region)
+    }
+
+    /// As `diff`, restricted to the pixels in `region`.
+    pub fn diff_region(&self, other: &FrameBuffer, region: Rect) -> i64 {
+        debug_assert_eq!(self.pixels.len(), other.pixels.len());
+
+        let region = region.intersect(&self.bounds());
+        if region.is_empty() {
+            return 0;
+        }
+        let w = self.width as usize;
         let mut d: i64 = 0;
-        for (chunk_a, chunk_b) in self.pixels.chunks(3).zip(other.pixels.chunks(3)) {
-            let dr = chunk_a[0] as i64 - chunk_b[0] as i64;
-            let dg = chunk_a[1] as i64 - chunk_b[1] as i64;
-            let db = chunk_a[2] as i64 - chunk_b[2] as i64;
-            d += ((dr * dr + dg * dg + db * db) as f64).sqrt() as i64;
+        for y in region.y0..region.y1 {
+            let row = (y * w + region.x0) * 3..(y * w + region.x1) * 3;
+            d += pixel_diff(&self.pixels[row.clone()], &other.pixels[row]);
         }
         d
     }
+
+    pub fn diff(&self, other: &FrameBuffer) -> i64 {
+        debug_assert_eq!(self.pixels.len(), other.pixels.len());
+        pixel_diff(&self.pixels, &other.pixels)
+    }
+}
+
+// sum of per-pixel Euclidean RGB distances, truncated per pixel
+fn pixel_diff(a: &[u8], b: &[u8]) -> i64 {
+    let mut d: i64 = 0;
+    for (chunk_a, chunk_b) in a.chunks(3).zip(b.chunks(3)) {
+        let dr = chunk_a[0] as i64 - chunk_b[0] as i64;
+        let dg = chunk_a[1] as i64 - chunk_b[1] as i64;
+        let db = chunk_a[2] as i64 - chunk_b[2] as i64;
+        d += ((dr * dr + dg * dg + db * db) as f64).sqrt() as i64;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShapeKind;
+
+    // opaque white right-angled triangle with its legs along the axes
+    fn triangle(legs: f32) -> Triangle {
+        let geometry = [0.0, 0.0, legs, 0.0, 0.0, legs];
+        Triangle::from_geometry(
+            ShapeKind::Triangle,
+            &geometry,
+            Colour::new(255, 255, 255, 100),
+        )
+        .unwrap()
+    }
+
+    fn red(fb: &FrameBuffer, x: usize, y: usize) -> u8 {
+        fb.pixels[(y * fb.width as usize + x) * 3]
+    }
+
+    #[test]
+    fn aliased_triangle_fills_the_inside() {
+        let mut fb = FrameBuffer::new(10, 10);
+        fb.draw_triangle(&triangle(8.0));
+        for (x, y) in [(0, 0), (1, 1), (6, 1), (0, 7), (3, 4)] {
+            assert_eq!(red(&fb, x, y), 255, "({x}, {y})");
+        }
+        for (x, y) in [(9, 0), (5, 5), (9, 9), (0, 9)] {
+            assert_eq!(red(&fb, x, y), 0, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn antialiased_triangle_covers_inside_fully() {
+        let mut fb = FrameBuffer::new(10, 10);
+        fb.antialias = true;
+        fb.draw_triangle(&triangle(8.0));
+        // pixels along the axis-aligned legs lie wholly inside
+        for (x, y) in [(0, 0), (1, 1), (0, 6), (6, 0), (2, 3)] {
+            assert_eq!(red(&fb, x, y), 255, "({x}, {y})");
+        }
+        for (x, y) in [(5, 5), (9, 0), (0, 9), (9, 9)] {
+            assert_eq!(red(&fb, x, y), 0, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn antialiased_triangle_covers_edges_partially() {
+        let mut fb = FrameBuffer::new(10, 10);
+        fb.antialias = true;
+        fb.draw_triangle(&triangle(8.0));
+        // the hypotenuse x + y = 8 runs through the centres of these pixels
+        for (x, y) in [(3, 4), (4, 3), (1, 6)] {
+            assert_eq!(red(&fb, x, y), 127, "({x}, {y})");
+        }
+        // a vertex half a pixel in gives half coverage of the column
+        let mut fb = FrameBuffer::new(10, 10);
+        fb.antialias = true;
+        let geometry = [0.0, 0.0, 0.0, 8.0, 4.5, 8.0];
+        let t = Triangle::from_geometry(
+            ShapeKind::Triangle,
+            &geometry,
+            Colour::new(255, 255, 255, 100),
+        )
+        .unwrap();
+        fb.draw_triangle(&t);
+        assert!((1..255).contains(&red(&fb, 4, 7)));
+    }
+
+    #[test]
+    fn redraw_region_keeps_the_background() {
+        let shapes = [triangle(8.0), triangle(5.0)];
+        let mut full = FrameBuffer::new(10, 10);
+        full.background = [10, 20, 30];
+        full.draw_shapes(&shapes);
+        let mut partial = FrameBuffer::new(10, 10);
+        partial.background = [10, 20, 30];
+        partial.redraw_region(&shapes, Rect::new(0, 0, 10, 10));
+        assert_eq!(partial.pixels, full.pixels);
+        assert_eq!(full.pixels[full.pixels.len() - 3..], [10, 20, 30]);
+    }
 }
@@ -0,0 +1,860 @@
+//! Genetic algorithm: a population of complete solutions, bred by
+//! tournament selection, one point crossover and mutation, keeping the best
+//! few unchanged from one generation to the next.
+//!
+//! The population can be split into islands that evolve on their own, so
+//! that they do not all converge on the same solution, and now and then
+//! send copies of their best individuals to their neighbours.
+
+use crate::checkpoint::{self, invalid};
+use crate::observer::{self, Observer};
+use crate::{Checkpoint, FrameBuffer, Json, Optimizer, Shape, Target, stream_seed};
+use mersenne_twister_rs::MersenneTwister64;
+use rand_core::RngCore;
+use std::io;
+
+/// Which islands send migrants to which
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    /// Each island to the next, the last to the first
+    Ring,
+    /// Every island to every other
+    Full,
+}
+
+named_enum!(Topology, "topology", {
+    Ring => "ring",
+    Full => "full",
+});
+
+impl Topology {
+    // The islands that island `i` of `n` sends migrants to
+    fn neighbours(self, i: usize, n: usize) -> Vec<usize> {
+        match self {
+            Topology::Ring if n > 1 => vec![(i + 1) % n],
+            Topology::Ring => Vec::new(),
+            Topology::Full => (0..n).filter(|&j| j != i).collect(),
+        }
+    }
+}
+
+/// Settings of a `GeneticAlgorithm` run
+#[derive(Clone, Debug)]
+pub struct GeneticConfig {
+    /// Number of individuals on each island
+    pub population: usize,
+    /// Number of islands
+    pub islands: usize,
+    /// Generations between migrations (0 = never)
+    pub migration_interval: u64,
+    /// Best individuals of an island copied to each neighbour in a migration
+    pub migrants: usize,
+    /// Which islands are neighbours
+    pub topology: Topology,
+    /// Shapes per individual
+    pub num_shapes: usize,
+    /// Probability that a shape of a child is mutated
+    pub mutation_rate: f64,
+    /// Individuals competing to be a parent
+    pub tournament_size: usize,
+    /// Number of the best individuals carried over unchanged
+    pub elitism: usize,
+    /// Worker threads for breeding and evaluation (0 = one per core)
+    pub threads: usize,
+    /// Anti-alias triangle edges
+    pub antialias: bool,
+    /// After a change to the geometry of a shape, give it the best colour
+    /// for its new place, see `Target::solve_colour`
+    pub solve_colour: bool,
+    pub seed: u64,
+}
+
+impl Default for GeneticConfig {
+    fn default() -> Self {
+        Self {
+            population: 50,
+            islands: 1,
+            migration_interval: 20,
+            migrants: 2,
+            topology: Topology::Ring,
+            num_shapes: 128,
+            mutation_rate: 0.05,
+            tournament_size: 3,
+            elitism: 2,
+            threads: 0,
+            antialias: false,
+            solve_colour: false,
+            seed: 42,
+        }
+    }
+}
+
+impl GeneticConfig {
+    pub fn population(mut self, population: usize) -> Self {
+        self.population = population;
+        self
+    }
+
+    pub fn islands(mut self, islands: usize) -> Self {
+        self.islands = islands;
+        self
+    }
+
+    pub fn migration_interval(mut self, migration_interval: u64) -> Self {
+        self.migration_interval = migration_interval;
+        self
+    }
+
+    pub fn migrants(mut self, migrants: usize) -> Self {
+        self.migrants = migrants;
+        self
+    }
+
+    pub fn topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn num_shapes(mut self, num_shapes: usize) -> Self {
+        self.num_shapes = num_shapes;
+        self
+    }
+
+    pub fn mutation_rate(mut self, mutation_rate: f64) -> Self {
+        self.mutation_rate = mutation_rate;
+        self
+    }
+
+    pub fn tournament_size(mut self, tournament_size: usize) -> Self {
+        self.tournament_size = tournament_size;
+        self
+    }
+
+    pub fn elitism(mut self, elitism: usize) -> Self {
+        self.elitism = elitism;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    pub fn solve_colour(mut self, solve_colour: bool) -> Self {
+        self.solve_colour = solve_colour;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Check that the settings are consistent
+    pub fn validate(&self) -> Result<(), String> {
+        if self.elitism >= self.population {
+            return Err("elitism must be less than population size".into());
+        }
+        if self.tournament_size == 0 {
+            return Err("tournament size must be at least 1".into());
+        }
+        if self.tournament_size > self.population {
+            return Err("tournament size must not exceed population size".into());
+        }
+        if self.num_shapes == 0 {
+            return Err("there must be at least one shape".into());
+        }
+        if self.islands == 0 {
+            return Err("there must be at least one island".into());
+        }
+        if self.islands > 1 && self.migrants > self.population - self.elitism {
+            return Err("migrants must not exceed population size minus elitism".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Individual<S: Shape> {
+    shapes: Vec<S>,
+    fitness: f64,
+}
+
+impl<S: Shape> Individual<S> {
+    fn new<R: RngCore>(rng: &mut R, num_shapes: usize, width: u16, height: u16) -> Self {
+        let shapes: Vec<S> = (0..num_shapes)
+            .map(|_| S::random(rng, width, height))
+            .collect();
+        Self {
+            shapes,
+            fitness: f64::MAX,
+        }
+    }
+
+    // `init` padded with random shapes up to `num_shapes`
+    fn from_shapes<R: RngCore>(
+        rng: &mut R,
+        init: &[S],
+        num_shapes: usize,
+        width: u16,
+        height: u16,
+    ) -> Self {
+        let mut shapes = init.to_vec();
+        while shapes.len() < num_shapes {
+            shapes.push(S::random(rng, width, height));
+        }
+        Self {
+            shapes,
+            fitness: f64::MAX,
+        }
+    }
+
+    fn evaluate(&mut self, fb: &mut FrameBuffer, target: &Target) {
+        fb.clear();
+        fb.draw_shapes(&self.shapes);
+        self.fitness = target.error(fb);
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("fitness", Json::number(self.fitness)),
+            ("shapes", checkpoint::shapes_to_json(&self.shapes)),
+        ])
+    }
+
+    fn from_json(json: &Json, width: u16, height: u16) -> io::Result<Self> {
+        let fitness = json.get("fitness").and_then(Json::as_f64);
+        let shapes = json
+            .get("shapes")
+            .map(|shapes| checkpoint::shapes_from_json(shapes, width, height));
+        match (fitness, shapes) {
+            (Some(fitness), Some(shapes)) => Ok(Self {
+                shapes: shapes?,
+                fitness,
+            }),
+            _ => Err(invalid("individual without fitness or shapes")),
+        }
+    }
+
+    // Returns the indices of the shapes whose geometry changed
+    fn mutate<R: RngCore>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        config: &GeneticConfig,
+    ) -> Vec<usize> {
+        let mut moved = Vec::new();
+        for (i, shape) in self.shapes.iter_mut().enumerate() {
+            if (rng.next_u64() as f64 / u64::MAX as f64) < config.mutation_rate {
+                let geometry = shape.geometry();
+                shape.mutate(rng, width, height);
+                if !config.antialias {
+                    shape.snap_to_pixels();
+                }
+                if shape.geometry() != geometry {
+                    moved.push(i);
+                }
+            }
+        }
+        moved
+    }
+
+    // Give the shapes at `indices` (ascending) their best colours, each
+    // over the shapes beneath it
+    fn solve_colours(&mut self, fb: &mut FrameBuffer, target: &Target, indices: &[usize]) {
+        let Some(&last) = indices.last() else {
+            return;
+        };
+        fb.clear();
+        for (i, shape) in self.shapes[..=last].iter_mut().enumerate() {
+            if indices.contains(&i) {
+                target.solve_colour(shape, fb);
+            }
+            shape.draw(fb);
+        }
+    }
+}
+
+fn crossover<S: Shape, R: RngCore>(
+    parent1: &Individual<S>,
+    parent2: &Individual<S>,
+    rng: &mut R,
+) -> Individual<S> {
+    let len = parent1.shapes.len();
+    let crossover_point = (rng.next_u64() % len as u64) as usize;
+
+    let mut child_shapes = Vec::with_capacity(len);
+    child_shapes.extend_from_slice(&parent1.shapes[..crossover_point]);
+    child_shapes.extend_from_slice(&parent2.shapes[crossover_point..]);
+
+    Individual {
+        shapes: child_shapes,
+        fitness: f64::MAX,
+    }
+}
+
+fn tournament_select<'a, S: Shape, R: RngCore>(
+    population: &'a [Individual<S>],
+    rng: &mut R,
+    tournament_size: usize,
+) -> &'a Individual<S> {
+    let mut best: Option<&Individual<S>> = None;
+
+    for _ in 0..tournament_size {
+        let idx = (rng.next_u64() % population.len() as u64) as usize;
+        let candidate = &population[idx];
+        if best.is_none() || candidate.fitness < best.unwrap().fitness {
+            best = Some(candidate);
+        }
+    }
+
+    best.unwrap()
+}
+
+// Evaluate individuals in parallel, one thread per framebuffer
+fn evaluate_all<S: Shape>(
+    population: &mut [Individual<S>],
+    fbs: &mut [FrameBuffer],
+    target: &Target,
+) {
+    let chunk_size = population.len().div_ceil(fbs.len()).max(1);
+    std::thread::scope(|scope| {
+        for (chunk, fb) in population.chunks_mut(chunk_size).zip(fbs.iter_mut()) {
+            scope.spawn(move || {
+                for individual in chunk {
+                    individual.evaluate(fb, target);
+                }
+            });
+        }
+    });
+}
+
+// Breed and evaluate `count` children of `population`, one thread per
+// framebuffer. Thread `t` draws from its own random stream derived from
+// (seed, generation, stream + t), so the result depends only on the seed
+// and the number of threads - not on scheduling.
+fn breed<S: Shape>(
+    population: &[Individual<S>],
+    count: usize,
+    generation: u64,
+    stream: u64,
+    fbs: &mut [FrameBuffer],
+    target: &Target,
+    config: &GeneticConfig,
+) -> Vec<Individual<S>> {
+    let (width, height) = (target.reference.width, target.reference.height);
+    let per_thread = count.div_ceil(fbs.len());
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = fbs
+            .iter_mut()
+            .enumerate()
+            .map(|(t, fb)| {
+                let n = per_thread.min(count.saturating_sub(t * per_thread));
+                scope.spawn(move || {
+                    let mut rng = MersenneTwister64::new(stream_seed(
+                        config.seed,
+                        generation,
+                        stream + t as u64,
+                    ));
+                    (0..n)
+                        .map(|_| {
+                            let parent1 =
+                                tournament_select(population, &mut rng, config.tournament_size);
+                            let parent2 =
+                                tournament_select(population, &mut rng, config.tournament_size);
+
+                            let mut child = crossover(parent1, parent2, &mut rng);
+                            let moved = child.mutate(&mut rng, width, height, config);
+                            if config.solve_colour {
+                                child.solve_colours(fb, target, &moved);
+                            }
+                            child.evaluate(fb, target);
+                            child
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("worker thread panicked"))
+            .collect()
+    })
+}
+
+// The generation after `population`: the elite carried over, then bred
+// children (see `breed`), sorted by fitness
+fn next_generation<S: Shape>(
+    population: &[Individual<S>],
+    generation: u64,
+    stream: u64,
+    fbs: &mut [FrameBuffer],
+    target: &Target,
+    config: &GeneticConfig,
+) -> Vec<Individual<S>> {
+    let mut new_population: Vec<Individual<S>> = Vec::with_capacity(config.population);
+
+    // new population = Elitism + crossover + mutation
+    for e in &population[..config.elitism] {
+        new_population.push(e.clone());
+    }
+
+    let count = config.population - new_population.len();
+    new_population.extend(breed(
+        population, count, generation, stream, fbs, target, config,
+    ));
+    new_population.sort_by(|a, b| a.fitness.total_cmp(&b.fitness));
+    new_population
+}
+
+// The best individual of all `islands`, each sorted best first
+fn leader<S: Shape>(islands: &[Vec<Individual<S>>]) -> &Individual<S> {
+    islands
+        .iter()
+        .map(|island| &island[0])
+        .min_by(|a, b| a.fitness.total_cmp(&b.fitness))
+        .expect("at least one island")
+}
+
+pub struct GeneticAlgorithm<S: Shape> {
+    config: GeneticConfig,
+    target: Target,
+    // each sorted by fitness, best first
+    islands: Vec<Vec<Individual<S>>>,
+    best_ever: Individual<S>,
+    generation: u64,
+    // one per worker thread
+    fbs: Vec<FrameBuffer>,
+    observers: Vec<Box<dyn Observer<S>>>,
+}
+
+impl<S: Shape> GeneticAlgorithm<S> {
+    /// Start from a population of random individuals
+    pub fn new(target: Target, config: GeneticConfig) -> io::Result<Self> {
+        Self::from_shapes(target, config, Vec::new())
+    }
+
+    /// Start from `shapes`, e.g. an earlier result: the first individual is
+    /// `shapes` padded with random shapes, the others mutated copies of it.
+    /// Without shapes all individuals are random.
+    pub fn from_shapes(target: Target, config: GeneticConfig, shapes: Vec<S>) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let (width, height) = (target.reference.width, target.reference.height);
+        let threads = match config.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let mut fbs = Self::framebuffers(&target, &config, threads);
+
+        let mut rng = MersenneTwister64::new(config.seed);
+        let total = config.population * config.islands;
+        let mut population: Vec<Individual<S>> = if shapes.is_empty() {
+            (0..total)
+                .map(|_| Individual::new(&mut rng, config.num_shapes, width, height))
+                .collect()
+        } else {
+            // every individual but the first is a mutated copy of the shapes
+            (0..total)
+                .map(|i| {
+                    let mut individual = Individual::from_shapes(
+                        &mut rng,
+                        &shapes,
+                        config.num_shapes,
+                        width,
+                        height,
+                    );
+                    if i > 0 {
+                        individual.mutate(&mut rng, width, height, &config);
+                    }
+                    individual
+                })
+                .collect()
+        };
+
+        evaluate_all(&mut population, &mut fbs, &target);
+        let islands: Vec<Vec<Individual<S>>> = population
+            .chunks(config.population)
+            .map(|island| {
+                let mut island = island.to_vec();
+                island.sort_by(|a, b| a.fitness.total_cmp(&b.fitness));
+                island
+            })
+            .collect();
+
+        Ok(Self {
+            best_ever: leader(&islands).clone(),
+            islands,
+            generation: 0,
+            config,
+            target,
+            fbs,
+            observers: Vec::new(),
+        })
+    }
+
+    /// Continue the run saved in `checkpoint`, which must have been made
+    /// with the same target and config
+    pub fn resume(
+        target: Target,
+        config: GeneticConfig,
+        checkpoint: &Checkpoint,
+    ) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // the offspring depend on the number of threads, so a resumed run
+        // keeps the count it started with even on a machine with more cores
+        let threads = match config.threads {
+            0 => checkpoint.u64("threads")? as usize,
+            n => n,
+        };
+        let fbs = Self::framebuffers(&target, &config, threads);
+        let (width, height) = (target.reference.width, target.reference.height);
+
+        // Each generation breeds from its own random streams (see `breed`),
+        // so the population is all the state there is to restore
+        let population = |json: &Json| -> io::Result<Vec<Individual<S>>> {
+            json.as_array()
+                .unwrap_or_default()
+                .iter()
+                .map(|individual| Individual::from_json(individual, width, height))
+                .collect()
+        };
+        // checkpoints from before the islands were added have a single
+        // population
+        let islands: Vec<Vec<Individual<S>>> = match checkpoint.state.get("islands") {
+            Some(islands) => islands
+                .as_array()
+                .unwrap_or_default()
+                .iter()
+                .map(population)
+                .collect::<io::Result<_>>()?,
+            None => vec![population(checkpoint.field("population")?)?],
+        };
+        if islands.len() != config.islands {
+            return Err(invalid(format!(
+                "checkpoint has {} islands, expected {}",
+                islands.len(),
+                config.islands
+            )));
+        }
+        if islands.iter().any(Vec::is_empty) {
+            return Err(invalid("checkpoint with an empty island"));
+        }
+
+        Ok(Self {
+            best_ever: Individual::from_json(checkpoint.field("best_ever")?, width, height)?,
+            islands,
+            generation: checkpoint.generation,
+            config,
+            target,
+            fbs,
+            observers: Vec::new(),
+        })
+    }
+
+    fn framebuffers(target: &Target, config: &GeneticConfig, threads: usize) -> Vec<FrameBuffer> {
+        (0..threads)
+            .map(|_| {
+                let mut fb = FrameBuffer::new(target.reference.width, target.reference.height);
+                fb.antialias = config.antialias;
+                fb
+            })
+            .collect()
+    }
+
+    pub fn config(&self) -> &GeneticConfig {
+        &self.config
+    }
+
+    /// Number of worker threads
+    pub fn threads(&self) -> usize {
+        self.fbs.len()
+    }
+
+    /// Error of the worst individual of the current generation
+    pub fn worst_fitness(&self) -> f64 {
+        self.islands
+            .iter()
+            .filter_map(|island| island.last())
+            .map(|i| i.fitness)
+            .fold(f64::MIN, f64::max)
+    }
+
+    /// Error of the best individual of each island
+    pub fn island_fitness(&self) -> Vec<f64> {
+        self.islands
+            .iter()
+            .map(|island| island[0].fitness)
+            .collect()
+    }
+
+    // The best individual of the current generation
+    fn leader(&self) -> &Individual<S> {
+        leader(&self.islands)
+    }
+
+    // Breed every island. With more than one, the islands are shared out
+    // between the threads and each is bred on one, from its own random
+    // stream, so the result does not depend on the number of threads.
+    fn breed_islands(&mut self) {
+        let (generation, target, config) = (self.generation, &self.target, &self.config);
+        if let [island] = self.islands.as_mut_slice() {
+            *island = next_generation(island, generation, 0, &mut self.fbs, target, config);
+            return;
+        }
+        let chunk_size = self.islands.len().div_ceil(self.fbs.len());
+        std::thread::scope(|scope| {
+            for (c, (chunk, fb)) in self
+                .islands
+                .chunks_mut(chunk_size)
+                .zip(self.fbs.iter_mut())
+                .enumerate()
+            {
+                scope.spawn(move || {
+                    for (k, island) in chunk.iter_mut().enumerate() {
+                        let stream = (c * chunk_size + k) as u64;
+                        let fbs = std::slice::from_mut(&mut *fb);
+                        *island = next_generation(island, generation, stream, fbs, target, config);
+                    }
+                });
+            }
+        });
+    }
+
+    // Copy the best `migrants` of every island to its neighbours, where the
+    // best arrivals replace the worst residents (never the elite)
+    fn migrate(&mut self) {
+        let n = self.islands.len();
+        let mut arrivals: Vec<Vec<Individual<S>>> = (0..n).map(|_| Vec::new()).collect();
+        for (i, island) in self.islands.iter().enumerate() {
+            let emigrants = &island[..self.config.migrants.min(island.len())];
+            for j in self.config.topology.neighbours(i, n) {
+                arrivals[j].extend_from_slice(emigrants);
+            }
+        }
+        for (island, mut arrivals) in self.islands.iter_mut().zip(arrivals) {
+            arrivals.sort_by(|a, b| a.fitness.total_cmp(&b.fitness));
+            arrivals.truncate(island.len().saturating_sub(self.config.elitism));
+            let start = island.len() - arrivals.len();
+            for (resident, arrival) in island[start..].iter_mut().zip(arrivals) {
+                *resident = arrival;
+            }
+            island.sort_by(|a, b| a.fitness.total_cmp(&b.fitness));
+        }
+    }
+}
+
+impl<S: Shape> Optimizer<S> for GeneticAlgorithm<S> {
+    fn step(&mut self) -> io::Result<()> {
+        self.breed_islands();
+        self.generation += 1;
+
+        if self.islands.len() > 1
+            && self.config.migration_interval > 0
+            && self
+                .generation
+                .is_multiple_of(self.config.migration_interval)
+        {
+            self.migrate();
+        }
+
+        if self.leader().fitness < self.best_ever.fitness {
+            self.best_ever = self.leader().clone();
+            observer::notify(self, |o, opt| o.on_improvement(opt))?;
+        }
+
+        observer::notify(self, |o, opt| o.on_generation(opt))
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn best(&self) -> &[S] {
+        &self.best_ever.shapes
+    }
+
+    fn best_fitness(&self) -> f64 {
+        self.best_ever.fitness
+    }
+
+    fn current(&self) -> &[S] {
+        &self.leader().shapes
+    }
+
+    fn fitness(&self) -> f64 {
+        self.leader().fitness
+    }
+
+    fn target(&self) -> &Target {
+        &self.target
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "best_ever={:.4}, gen_best={:.4}, gen_worst={:.4}",
+            self.target.normalise(self.best_ever.fitness),
+            self.target.normalise(self.leader().fitness),
+            self.target.normalise(self.worst_fitness())
+        )
+    }
+
+    fn snapshot(&self) -> Json {
+        let population = |island: &Vec<Individual<S>>| {
+            Json::Array(island.iter().map(Individual::to_json).collect())
+        };
+        Json::object(vec![
+            (
+                "islands",
+                Json::Array(self.islands.iter().map(population).collect()),
+            ),
+            ("threads", Json::number(self.threads())),
+            ("best_ever", self.best_ever.to_json()),
+        ])
+    }
+
+    fn observers(&mut self) -> &mut Vec<Box<dyn Observer<S>>> {
+        &mut self.observers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetricKind, Primitive};
+
+    fn target() -> Target {
+        let mut reference = FrameBuffer::new(24, 16);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % 24, i / 24);
+            px.copy_from_slice(&[(x * 10) as u8, (y * 15) as u8, 128]);
+        }
+        Target::new(reference, MetricKind::Euclidean)
+    }
+
+    fn config() -> GeneticConfig {
+        GeneticConfig::default()
+            .population(8)
+            .num_shapes(6)
+            .mutation_rate(0.2)
+            .seed(7)
+    }
+
+    fn run(config: GeneticConfig, generations: u64) -> GeneticAlgorithm<Primitive> {
+        let mut ga = GeneticAlgorithm::new(target(), config).unwrap();
+        for _ in 0..generations {
+            ga.step().unwrap();
+        }
+        ga
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_settings() {
+        assert!(config().validate().is_ok());
+        for bad in [
+            config().tournament_size(0),
+            config().tournament_size(9),
+            config().num_shapes(0),
+            config().elitism(8),
+            config().islands(0),
+            config().islands(2).migrants(7),
+        ] {
+            assert!(bad.validate().is_err(), "{bad:?}");
+            assert!(GeneticAlgorithm::<Primitive>::new(target(), bad).is_err());
+        }
+        assert!(config().islands(2).migrants(6).validate().is_ok());
+    }
+
+    #[test]
+    fn same_seed_and_threads_give_the_same_run() {
+        let a = run(config().threads(3), 10);
+        let b = run(config().threads(3), 10);
+        assert_eq!(a.best_fitness(), b.best_fitness());
+        let geometry = |ga: &GeneticAlgorithm<Primitive>| -> Vec<_> {
+            ga.best()
+                .iter()
+                .map(|s| (s.kind(), s.geometry(), s.colour().rgba()))
+                .collect()
+        };
+        assert_eq!(geometry(&a), geometry(&b));
+    }
+
+    #[test]
+    fn any_thread_count_builds_valid_populations() {
+        for threads in [1, 4] {
+            let ga = run(config().threads(threads), 5);
+            assert_eq!(ga.threads(), threads);
+            let island = &ga.islands[0];
+            assert_eq!(island.len(), 8);
+            assert!(island.is_sorted_by(|a, b| a.fitness <= b.fitness));
+            let mut fb = FrameBuffer::new(24, 16);
+            for individual in island {
+                assert_eq!(individual.shapes.len(), 6);
+                fb.draw_shapes(&individual.shapes);
+                assert_eq!(
+                    individual.fitness,
+                    ga.target.error(&fb),
+                    "{threads} threads"
+                );
+            }
+            assert!(ga.best_fitness() <= island[0].fitness);
+        }
+    }
+
+    // The fitness and shapes of every individual of every island
+    type Islands = Vec<Vec<(f64, Vec<Vec<f32>>)>>;
+
+    fn islands(ga: &GeneticAlgorithm<Primitive>) -> Islands {
+        let individual =
+            |i: &Individual<Primitive>| (i.fitness, i.shapes.iter().map(Shape::geometry).collect());
+        ga.islands
+            .iter()
+            .map(|island| island.iter().map(individual).collect())
+            .collect()
+    }
+
+    // Run three islands with and without a migration after the last
+    // generation, and check that each island is that of the run without,
+    // with its worst `replaced` individuals swapped for the best two of
+    // each island in `sources(island)`
+    fn check_migration(topology: Topology, sources: fn(usize) -> Vec<usize>, replaced: usize) {
+        let config = config().islands(3).migrants(2).topology(topology);
+        let generations = 4;
+        let before = run(config.clone().migration_interval(0), generations);
+        let after = run(config.clone().migration_interval(generations), generations);
+        let (before, after) = (islands(&before), islands(&after));
+        assert_ne!(before, after);
+        // no migration before the interval
+        let early = run(config.clone().migration_interval(0), generations - 1);
+        let not_yet = run(config.migration_interval(generations), generations - 1);
+        assert_eq!(islands(&early), islands(&not_yet));
+
+        for (j, island) in after.iter().enumerate() {
+            let mut expected = before[j][..8 - replaced].to_vec();
+            for i in sources(j) {
+                expected.extend_from_slice(&before[i][..2]);
+            }
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+            assert_eq!(island, &expected, "island {j}");
+        }
+    }
+
+    #[test]
+    fn ring_migrants_replace_the_worst_of_the_next_island() {
+        check_migration(Topology::Ring, |j| vec![(j + 2) % 3], 2);
+    }
+
+    #[test]
+    fn full_migrants_replace_the_worst_of_every_other_island() {
+        check_migration(Topology::Full, |j| (0..3).filter(|&i| i != j).collect(), 4);
+    }
+}
@@ -0,0 +1,411 @@
+//! Saved solutions: the shapes, the canvas size and how they were found.
+//!
+//! Two encodings of the same data are supported - human readable JSON and
+//! a compact binary format. `load` tells them apart by the magic bytes at
+//! the start of the binary format; `save` picks one from the file extension.
+//! `load` also reads the shapes back from an SVG written by `save_svg`.
+
+use crate::checkpoint::{self, invalid};
+use crate::json::Json;
+use crate::svg;
+use crate::{Colour, MetricKind, Shape, ShapeKind};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const FORMAT: &str = "shapeme-genome";
+const VERSION: u64 = 1;
+const MAGIC: &[u8; 4] = b"SHPG";
+
+/// A solution together with the canvas it was made for
+#[derive(Clone)]
+pub struct Genome<S> {
+    pub width: u16,
+    pub height: u16,
+    pub shapes: Vec<S>,
+    /// Metric the fitness was measured with
+    pub metric: Option<MetricKind>,
+    /// Seed of the run that produced the shapes
+    pub seed: Option<u64>,
+    /// Error per pixel, as reported in the logs
+    pub fitness: Option<f64>,
+}
+
+impl<S: Shape> Genome<S> {
+    pub fn new(width: u16, height: u16, shapes: Vec<S>) -> Self {
+        Self {
+            width,
+            height,
+            shapes,
+            metric: None,
+            seed: None,
+            fitness: None,
+        }
+    }
+
+    /// Save as JSON if `path` ends in `.json`, in the binary format otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        {
+            fs::write(path, format!("{}\n", self.to_json()))
+        } else {
+            let mut w = io::BufWriter::new(fs::File::create(path)?);
+            self.write_binary(&mut w)?;
+            w.flush()
+        }
+    }
+
+    /// Load a genome saved in either format, or the shapes of an SVG
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(MAGIC) {
+            return Self::read_binary(&mut bytes.as_slice());
+        }
+        let text = String::from_utf8(bytes).map_err(|_| invalid("not a genome file"))?;
+        if text.trim_start().starts_with('<') {
+            svg::parse_svg(&text)
+        } else {
+            Self::from_json(&Json::parse(&text).map_err(invalid)?)
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("format", Json::String(FORMAT.to_string())),
+            ("version", Json::number(VERSION)),
+            ("width", Json::number(self.width)),
+            ("height", Json::number(self.height)),
+        ];
+        if let Some(metric) = self.metric {
+            fields.push(("metric", Json::String(metric.to_string())));
+        }
+        if let Some(seed) = self.seed {
+            fields.push(("seed", Json::number(seed)));
+        }
+        if let Some(fitness) = self.fitness {
+            fields.push(("fitness", Json::number(fitness)));
+        }
+        fields.push(("shapes", checkpoint::shapes_to_json(&self.shapes)));
+        Json::object(fields)
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Self> {
+        if json.get("format").and_then(Json::as_str) != Some(FORMAT) {
+            return Err(invalid("not a genome file"));
+        }
+        match json.get("version").and_then(Json::as_u64) {
+            Some(VERSION) => {}
+            v => return Err(invalid(format!("unsupported genome version {v:?}"))),
+        }
+        let size = |key| {
+            json.get(key)
+                .and_then(Json::as_u64)
+                .and_then(|v| u16::try_from(v).ok())
+                .filter(|&v| v > 0)
+                .ok_or_else(|| invalid(format!("genome without {key}")))
+        };
+        let metric = match json.get("metric").and_then(Json::as_str) {
+            Some(name) => Some(name.parse().map_err(invalid)?),
+            None => None,
+        };
+        let (width, height) = (size("width")?, size("height")?);
+        let shapes = checkpoint::shapes_from_json(
+            json.get("shapes")
+                .ok_or_else(|| invalid("genome without shapes"))?,
+            width,
+            height,
+        )?;
+        Ok(Self {
+            width,
+            height,
+            shapes,
+            metric,
+            seed: json.get("seed").and_then(Json::as_u64),
+            fitness: json.get("fitness").and_then(Json::as_f64),
+        })
+    }
+
+    /// Binary format, all numbers little endian:
+    ///
+    /// ```text
+    /// "SHPG" version:u8 width:u16 height:u16
+    /// metric:u8 (0 = none, else 1 + index in MetricKind::ALL)
+    /// has_seed:u8 [seed:u64] has_fitness:u8 [fitness:f64]
+    /// count:u32, then per shape:
+    ///     kind:u8 (index in ShapeKind::ALL) n:u8 geometry:n*f32 r:u8 g:u8 b:u8 alpha:u8
+    /// ```
+    pub fn write_binary<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION as u8])?;
+        w.write_all(&self.width.to_le_bytes())?;
+        w.write_all(&self.height.to_le_bytes())?;
+        let metric = self
+            .metric
+            .and_then(|m| MetricKind::ALL.iter().position(|&k| k == m))
+            .map_or(0, |i| i as u8 + 1);
+        w.write_all(&[metric])?;
+        match self.seed {
+            Some(seed) => {
+                w.write_all(&[1])?;
+                w.write_all(&seed.to_le_bytes())?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        match self.fitness {
+            Some(fitness) => {
+                w.write_all(&[1])?;
+                w.write_all(&fitness.to_le_bytes())?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        let count = u32::try_from(self.shapes.len()).map_err(|_| invalid("too many shapes"))?;
+        w.write_all(&count.to_le_bytes())?;
+        for s in &self.shapes {
+            let kind = ShapeKind::ALL.iter().position(|&k| k == s.kind()).unwrap();
+            let geometry = s.geometry();
+            w.write_all(&[kind as u8, geometry.len() as u8])?;
+            for v in geometry {
+                w.write_all(&v.to_le_bytes())?;
+            }
+            w.write_all(&s.colour().rgba())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_binary<R: Read>(r: &mut R) -> io::Result<Self> {
+        if read_bytes::<4, _>(r)? != *MAGIC {
+            return Err(invalid("not a genome file"));
+        }
+        let [version] = read_bytes(r)?;
+        if version as u64 != VERSION {
+            return Err(invalid(format!("unsupported genome version {version}")));
+        }
+        let width = u16::from_le_bytes(read_bytes(r)?);
+        let height = u16::from_le_bytes(read_bytes(r)?);
+        if width == 0 || height == 0 {
+            return Err(invalid("genome of an empty canvas"));
+        }
+        let metric = match read_bytes(r)? {
+            [0] => None,
+            [i] => Some(
+                *MetricKind::ALL
+                    .get(i as usize - 1)
+                    .ok_or_else(|| invalid(format!("unknown metric {i}")))?,
+            ),
+        };
+        let seed = match read_bytes(r)? {
+            [0] => None,
+            _ => Some(u64::from_le_bytes(read_bytes(r)?)),
+        };
+        let fitness = match read_bytes(r)? {
+            [0] => None,
+            _ => Some(f64::from_le_bytes(read_bytes(r)?)),
+        };
+        let count = u32::from_le_bytes(read_bytes(r)?);
+        let mut shapes = Vec::with_capacity(count.min(1 << 16) as usize);
+        for _ in 0..count {
+            let [kind, n] = read_bytes(r)?;
+            let kind = *ShapeKind::ALL
+                .get(kind as usize)
+                .ok_or_else(|| invalid(format!("unknown shape kind {kind}")))?;
+            let geometry = (0..n)
+                .map(|_| read_bytes(r).map(f32::from_le_bytes))
+                .collect::<io::Result<Vec<_>>>()?;
+            let [red, green, blue, alpha] = read_bytes(r)?;
+            let colour = Colour::new(red, green, blue, alpha);
+            let mut shape = S::from_geometry(kind, &geometry, colour)
+                .ok_or_else(|| invalid(format!("unexpected {kind} geometry {geometry:?}")))?;
+            shape.normalise(width, height);
+            shapes.push(shape);
+        }
+        Ok(Self {
+            width,
+            height,
+            shapes,
+            metric,
+            seed,
+            fitness,
+        })
+    }
+}
+
+fn read_bytes<const N: usize, R: Read>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Primitive;
+
+    fn genome() -> Genome<Primitive> {
+        let shapes = [
+            (ShapeKind::Triangle, &[1.0, 2.0, 8.0, 3.0, 4.0, 9.0][..]),
+            (ShapeKind::Circle, &[5.0, 6.0, 3.0]),
+            (ShapeKind::Ellipse, &[5.0, 6.0, 3.0, 2.0]),
+            (ShapeKind::Rectangle, &[1.0, 2.0, 7.0, 8.0]),
+            (ShapeKind::RotatedRectangle, &[5.0, 6.0, 3.0, 2.0, 30.0]),
+        ]
+        .map(|(kind, geometry)| {
+            Primitive::from_geometry(kind, geometry, Colour::new(10, 20, 30, 40)).unwrap()
+        });
+        let mut genome = Genome::new(32, 24, shapes.to_vec());
+        genome.metric = Some(MetricKind::Ssim);
+        genome.seed = Some(99);
+        genome.fitness = Some(0.125);
+        genome
+    }
+
+    fn assert_same(a: &Genome<Primitive>, b: &Genome<Primitive>) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        assert_eq!((a.metric, a.seed, a.fitness), (b.metric, b.seed, b.fitness));
+        let shapes = |g: &Genome<Primitive>| -> Vec<_> {
+            g.shapes
+                .iter()
+                .map(|s| (s.kind(), s.geometry(), s.colour().rgba()))
+                .collect()
+        };
+        assert_eq!(shapes(a), shapes(b));
+    }
+
+    fn from_json(text: &str) -> io::Result<Genome<Primitive>> {
+        Genome::from_json(&Json::parse(text).map_err(invalid)?)
+    }
+
+    fn json_with_shape(shape: &str) -> String {
+        format!(
+            r#"{{"format":"shapeme-genome","version":1,"width":100,"height":100,"shapes":[{shape}]}}"#
+        )
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let genome = genome();
+        let copy = from_json(&genome.to_json().to_string()).unwrap();
+        assert_same(&genome, &copy);
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let genome = genome();
+        let mut bytes = Vec::new();
+        genome.write_binary(&mut bytes).unwrap();
+        let copy = Genome::read_binary(&mut bytes.as_slice()).unwrap();
+        assert_same(&genome, &copy);
+    }
+
+    #[test]
+    fn loaded_shapes_are_normalised() {
+        let genome = from_json(&json_with_shape(
+            r#"{"kind":"rectangle","geometry":[200,200,10,10],"colour":[1,2,3,50]}"#,
+        ))
+        .unwrap();
+        assert_eq!(genome.shapes[0].geometry(), vec![10.0, 10.0, 99.0, 99.0]);
+        // and written from the top left corner
+        let mut svg = Vec::new();
+        genome.shapes[0].write_svg(&mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(
+            svg.starts_with(r#"<rect x="10" y="10" width="90" height="90" "#),
+            "{svg}"
+        );
+
+        let genome = from_json(&json_with_shape(
+            r#"{"kind":"triangle","geometry":[1,9,2,5,3,1],"colour":[1,2,3,50]}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            genome.shapes[0].geometry(),
+            vec![3.0, 1.0, 2.0, 5.0, 1.0, 9.0]
+        );
+    }
+
+    #[test]
+    fn binary_shapes_are_normalised() {
+        let rectangle = Primitive::from_geometry(
+            ShapeKind::Rectangle,
+            &[200.0, 200.0, 10.0, 10.0],
+            Colour::new(1, 2, 3, 50),
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        Genome::new(100, 100, vec![rectangle])
+            .write_binary(&mut bytes)
+            .unwrap();
+        let genome: Genome<Primitive> = Genome::read_binary(&mut bytes.as_slice()).unwrap();
+        assert_eq!(genome.shapes[0].geometry(), vec![10.0, 10.0, 99.0, 99.0]);
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        for shape in [
+            r#"{"kind":"circle","geometry":[1,2],"colour":[1,2,3,50]}"#,
+            r#"{"kind":"circle","geometry":[1,2,-3],"colour":[1,2,3,50]}"#,
+            r#"{"kind":"rectangle","geometry":[1,2,3,70000],"colour":[1,2,3,50]}"#,
+            r#"{"kind":"triangle","geometry":[1,2,3,4,5,-6],"colour":[1,2,3,50]}"#,
+            r#"{"kind":"hexagon","geometry":[1,2,3],"colour":[1,2,3,50]}"#,
+            r#"{"kind":"circle","geometry":[1,2,3],"colour":[1,2,3]}"#,
+            r#"{"kind":"mixed","geometry":[1,2,3],"colour":[1,2,3,50]}"#,
+        ] {
+            assert!(from_json(&json_with_shape(shape)).is_err(), "{shape}");
+        }
+        assert!(
+            from_json(
+                r#"{"format":"shapeme-genome","version":1,"width":0,"height":5,"shapes":[]}"#
+            )
+            .is_err()
+        );
+        assert!(
+            from_json(
+                r#"{"format":"shapeme-genome","version":2,"width":5,"height":5,"shapes":[]}"#
+            )
+            .is_err()
+        );
+        assert!(
+            from_json(r#"{"format":"other","version":1,"width":5,"height":5,"shapes":[]}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_binary() {
+        let mut bytes = Vec::new();
+        genome().write_binary(&mut bytes).unwrap();
+        let read = |bytes: &[u8]| Genome::<Primitive>::read_binary(&mut &bytes[..]);
+
+        // truncated
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        // version
+        let mut bad = bytes.clone();
+        bad[4] = 9;
+        assert!(read(&bad).is_err());
+        // empty canvas
+        let mut bad = bytes.clone();
+        bad[5..7].copy_from_slice(&0u16.to_le_bytes());
+        assert!(read(&bad).is_err());
+
+        // a circle with a negative radius, and with a value missing
+        let circle = |geometry: &[f32]| {
+            let mut bytes = Vec::new();
+            Genome::<Primitive>::new(10, 10, Vec::new())
+                .write_binary(&mut bytes)
+                .unwrap();
+            let count = bytes.len() - 4;
+            bytes[count..].copy_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&[1, geometry.len() as u8]);
+            for v in geometry {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[1, 2, 3, 50]);
+            bytes
+        };
+        assert!(read(&circle(&[1.0, 2.0, 3.0])).is_ok());
+        assert!(read(&circle(&[1.0, 2.0, -3.0])).is_err());
+        assert!(read(&circle(&[1.0, 2.0])).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Greedy placement in the style of fogleman/primitive: every generation
+//! adds the one shape that best improves the image so far. Many random
+//! candidates are scored against the current canvas, the best few are
+//! refined by hill climbing, and the winner is drawn for good. Shapes are
+//! never revisited once placed.
+
+use crate::checkpoint;
+use crate::observer::{self, Observer};
+use crate::{Checkpoint, CountingRng, FrameBuffer, Json, Optimizer, Shape, Target};
+use mersenne_twister_rs::MersenneTwister64;
+use std::io;
+
+/// Settings of a `Greedy` run
+#[derive(Clone, Debug)]
+pub struct GreedyConfig {
+    /// Maximum number of shapes
+    pub num_shapes: usize,
+    /// Random shapes tried for each new shape
+    pub candidates: usize,
+    /// Number of the best candidates refined by hill climbing
+    pub climbers: usize,
+    /// Mutations tried on each climber
+    pub climb_iterations: usize,
+    /// Anti-alias triangle edges
+    pub antialias: bool,
+    /// Give every candidate, and every change to its geometry while
+    /// climbing, the best colour for its place, see `Target::solve_colour`
+    pub solve_colour: bool,
+    pub seed: u64,
+}
+
+impl Default for GreedyConfig {
+    fn default() -> Self {
+        Self {
+            num_shapes: 128,
+            candidates: 500,
+            climbers: 3,
+            climb_iterations: 200,
+            antialias: false,
+            solve_colour: false,
+            seed: 42,
+        }
+    }
+}
+
+impl GreedyConfig {
+    pub fn num_shapes(mut self, num_shapes: usize) -> Self {
+        self.num_shapes = num_shapes;
+        self
+    }
+
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    pub fn climbers(mut self, climbers: usize) -> Self {
+        self.climbers = climbers;
+        self
+    }
+
+    pub fn climb_iterations(mut self, climb_iterations: usize) -> Self {
+        self.climb_iterations = climb_iterations;
+        self
+    }
+
+    pub fn antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    pub fn solve_colour(mut self, solve_colour: bool) -> Self {
+        self.solve_colour = solve_colour;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+pub struct Greedy<S: Shape> {
+    config: GreedyConfig,
+    target: Target,
+    rng: CountingRng<MersenneTwister64>,
+    shapes: Vec<S>,
+    diff: f64,
+    generation: u64,
+    // the rendering of `shapes`
+    fb: FrameBuffer,
+    observers: Vec<Box<dyn Observer<S>>>,
+}
+
+impl<S: Shape> Greedy<S> {
+    /// Start from a blank canvas
+    pub fn new(target: Target, config: GreedyConfig) -> Self {
+        Self::from_shapes(target, config, Vec::new())
+    }
+
+    /// Start from `shapes`, e.g. an earlier result, adding to them
+    pub fn from_shapes(target: Target, config: GreedyConfig, shapes: Vec<S>) -> Self {
+        let (width, height) = (target.reference.width, target.reference.height);
+        let rng = CountingRng::new(MersenneTwister64::new(config.seed));
+        let mut fb = FrameBuffer::new(width, height);
+        fb.antialias = config.antialias;
+        fb.draw_shapes(&shapes);
+        Self {
+            diff: target.error(&fb),
+            config,
+            target,
+            rng,
+            shapes,
+            generation: 0,
+            fb,
+            observers: Vec::new(),
+        }
+    }
+
+    /// Continue the run saved in `checkpoint`, which must have been made
+    /// with the same target and config
+    pub fn resume(
+        target: Target,
+        config: GreedyConfig,
+        checkpoint: &Checkpoint,
+    ) -> io::Result<Self> {
+        let rng = CountingRng::resume(
+            MersenneTwister64::new(config.seed),
+            checkpoint.u64("rng_draws")?,
+        );
+        let (width, height) = (target.reference.width, target.reference.height);
+        let shapes = checkpoint::shapes_from_json(checkpoint.field("shapes")?, width, height)?;
+        let mut greedy = Self::from_shapes(target, config, shapes);
+        greedy.rng = rng;
+        // as totalled while adding, see SimulatedAnnealing::resume
+        greedy.diff = checkpoint.f64("diff")?;
+        greedy.generation = checkpoint.generation;
+        Ok(greedy)
+    }
+
+    pub fn config(&self) -> &GreedyConfig {
+        &self.config
+    }
+
+    // How much drawing `shape` on top of the canvas changes the error; the
+    // canvas is left as it was
+    fn gain(&mut self, shape: &S) -> f64 {
+        let region = shape.bounds();
+        let saved = self.fb.copy_region(region);
+        let before = self.target.error_region(&self.fb, region);
+        shape.draw(&mut self.fb);
+        let after = self.target.error_region(&self.fb, region);
+        self.fb.paste_region(region, &saved);
+        after - before
+    }
+
+    // `shape` improved by random mutations, each kept if it helps, and its gain
+    fn climb(&mut self, mut shape: S, mut gain: f64) -> (S, f64) {
+        let (width, height) = (self.target.reference.width, self.target.reference.height);
+        for _ in 0..self.config.climb_iterations {
+            let mut candidate = shape.clone();
+            candidate.mutate(&mut self.rng, width, height);
+            if !self.config.antialias {
+                candidate.snap_to_pixels();
+            }
+            if self.config.solve_colour && candidate.geometry() != shape.geometry() {
+                self.target.solve_colour(&mut candidate, &mut self.fb);
+            }
+            let g = self.gain(&candidate);
+            if g < gain {
+                shape = candidate;
+                gain = g;
+            }
+        }
+        (shape, gain)
+    }
+}
+
+impl<S: Shape> Optimizer<S> for Greedy<S> {
+    fn step(&mut self) -> io::Result<()> {
+        let (width, height) = (self.target.reference.width, self.target.reference.height);
+        self.generation += 1;
+
+        if self.shapes.len() < self.config.num_shapes {
+            let mut candidates: Vec<(S, f64)> = (0..self.config.candidates.max(1))
+                .map(|_| {
+                    let mut shape = S::random(&mut self.rng, width, height);
+                    if self.config.solve_colour {
+                        self.target.solve_colour(&mut shape, &mut self.fb);
+                    }
+                    let gain = self.gain(&shape);
+                    (shape, gain)
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+            candidates.truncate(self.config.climbers.max(1));
+
+            let mut best: Option<(S, f64)> = None;
+            for (shape, gain) in candidates {
+                let climbed = self.climb(shape, gain);
+                if best.as_ref().is_none_or(|b| climbed.1 < b.1) {
+                    best = Some(climbed);
+                }
+            }
+
+            // a shape that would make things worse is not added
+            if let Some((shape, gain)) = best
+                && gain < 0.0
+            {
+                shape.draw(&mut self.fb);
+                self.diff += gain;
+                self.shapes.push(shape);
+                observer::notify(self, |o, opt| o.on_shape_added(opt))?;
+                observer::notify(self, |o, opt| o.on_improvement(opt))?;
+            }
+        }
+
+        observer::notify(self, |o, opt| o.on_generation(opt))
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn best(&self) -> &[S] {
+        &self.shapes
+    }
+
+    fn best_fitness(&self) -> f64 {
+        self.diff
+    }
+
+    fn current(&self) -> &[S] {
+        &self.shapes
+    }
+
+    fn fitness(&self) -> f64 {
+        self.diff
+    }
+
+    fn target(&self) -> &Target {
+        &self.target
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "diff={:.4}, shapes={}",
+            self.target.normalise(self.diff),
+            self.shapes.len()
+        )
+    }
+
+    fn snapshot(&self) -> Json {
+        Json::object(vec![
+            ("rng_draws", Json::number(self.rng.draws())),
+            ("diff", Json::number(self.diff)),
+            ("shapes", checkpoint::shapes_to_json(&self.shapes)),
+        ])
+    }
+
+    fn observers(&mut self) -> &mut Vec<Box<dyn Observer<S>>> {
+        &mut self.observers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetricKind, Primitive, Triangle};
+
+    fn target() -> Target {
+        let mut reference = FrameBuffer::new(24, 16);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % 24, i / 24);
+            px.copy_from_slice(&[(x * 10) as u8, (y * 15) as u8, 128]);
+        }
+        Target::new(reference, MetricKind::Euclidean)
+    }
+
+    fn config() -> GreedyConfig {
+        GreedyConfig::default()
+            .num_shapes(8)
+            .candidates(40)
+            .climb_iterations(30)
+            .seed(4)
+    }
+
+    // Step `greedy` through `generations`, checking every step that the
+    // error never rises and is that of a full redraw of the shapes
+    fn check<S: Shape>(mut greedy: Greedy<S>, generations: u64) {
+        let mut fb = FrameBuffer::new(24, 16);
+        fb.antialias = greedy.config().antialias;
+        fb.draw_shapes(greedy.best());
+        let mut last = greedy.best_fitness();
+        assert_eq!(last, greedy.target.error(&fb));
+        for _ in 0..generations {
+            let shapes = greedy.best().len();
+            greedy.step().unwrap();
+            let fitness = greedy.best_fitness();
+            assert!(fitness <= last, "{fitness} > {last}");
+            assert_eq!(fitness < last, greedy.best().len() == shapes + 1);
+            last = fitness;
+
+            fb.draw_shapes(greedy.best());
+            let error = greedy.target.error(&fb);
+            assert!(
+                (fitness - error).abs() <= 1e-9 * error,
+                "{fitness} != {error}"
+            );
+            assert_eq!(fb.pixels, greedy.fb.pixels);
+        }
+        assert_eq!(greedy.generation(), generations);
+        assert!(greedy.best().len() <= 8);
+        assert!(!greedy.best().is_empty());
+    }
+
+    #[test]
+    fn every_added_shape_lowers_the_error() {
+        check(Greedy::<Triangle>::new(target(), config()), 10);
+        check(Greedy::<Primitive>::new(target(), config()), 10);
+        check(
+            Greedy::<Triangle>::new(target(), config().antialias(true)),
+            10,
+        );
+        check(
+            Greedy::<Primitive>::new(target(), config().solve_colour(true)),
+            10,
+        );
+    }
+
+    #[test]
+    fn runs_from_earlier_shapes() {
+        let mut first = Greedy::<Primitive>::new(target(), config().num_shapes(3));
+        for _ in 0..3 {
+            first.step().unwrap();
+        }
+        let shapes = first.best().to_vec();
+        let greedy = Greedy::from_shapes(target(), config(), shapes);
+        assert_eq!(greedy.best_fitness(), first.best_fitness());
+        check(greedy, 6);
+    }
+}
@@ -0,0 +1,47 @@
+//! Graceful handling of Ctrl-C (SIGINT) and SIGTERM.
+//!
+//! Once `install` has been called, the first signal only sets a flag, which
+//! `StopCriteria::check` turns into `StopReason::Interrupted` so that the run
+//! ends after the current generation and its results are saved. A second
+//! signal exits immediately. Elsewhere than on Unix signals keep their
+//! default behaviour.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::c_int;
+
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+
+    unsafe extern "C" {
+        pub fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        pub fn _exit(status: c_int) -> !;
+    }
+
+    // only async-signal-safe calls in here
+    pub extern "C" fn handle(signum: c_int) {
+        if super::SIGNALS.fetch_add(1, super::Ordering::SeqCst) > 0 {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { _exit(128 + signum) }
+        }
+    }
+}
+
+/// Catch SIGINT and SIGTERM from now on
+pub fn install() {
+    #[cfg(unix)]
+    // SAFETY: the handler only touches an atomic and calls _exit
+    unsafe {
+        sys::signal(sys::SIGINT, sys::handle);
+        sys::signal(sys::SIGTERM, sys::handle);
+    }
+}
+
+/// Whether a signal has asked the run to stop
+pub fn requested() -> bool {
+    SIGNALS.load(Ordering::SeqCst) > 0
+}
@@ -0,0 +1,225 @@
+use crate::{FrameBuffer, Rect, Shape, Target};
+
+/// Snapshots of the composited image after every `interval` shapes.
+///
+/// Blending is order dependent, so changing shape `idx` only affects the
+/// image from layer `idx` upwards. Starting from the nearest snapshot below
+/// `idx`, only the remaining shapes need to be replayed to render a change.
+///
+/// The cache describes one particular list of shapes; after that list changes
+/// it must be brought up to date with `refresh` (one shape changed) or
+/// `rebuild` (anything else, e.g. a shape added).
+pub struct LayerCache {
+    interval: usize,
+    // layers[j] is the composite of the first (j + 1) * interval shapes
+    layers: Vec<FrameBuffer>,
+    width: u16,
+    height: u16,
+    antialias: bool,
+    background: [u8; 3],
+}
+
+impl LayerCache {
+    /// An empty cache for images of the same size and rendering settings as
+    /// `fb`. An `interval` of 0 disables the snapshots, everything is then
+    /// replayed from the first shape.
+    pub fn new(fb: &FrameBuffer, interval: usize) -> Self {
+        Self {
+            interval,
+            layers: Vec::new(),
+            width: fb.width,
+            height: fb.height,
+            antialias: fb.antialias,
+            background: fb.background,
+        }
+    }
+
+    // a cleared framebuffer with the rendering settings of the cache
+    fn framebuffer(&self) -> FrameBuffer {
+        let mut fb = FrameBuffer::new(self.width, self.height);
+        fb.antialias = self.antialias;
+        fb.background = self.background;
+        fb.clear();
+        fb
+    }
+
+    /// Re-renders all snapshots for `shapes` from scratch.
+    pub fn rebuild<S: Shape>(&mut self, shapes: &[S]) {
+        self.layers.clear();
+        if self.interval == 0 {
+            return;
+        }
+        let mut fb = self.framebuffer();
+        for chunk in shapes.chunks_exact(self.interval) {
+            for s in chunk {
+                s.draw(&mut fb);
+            }
+            let mut layer = self.framebuffer();
+            layer.pixels.copy_from_slice(&fb.pixels);
+            self.layers.push(layer);
+        }
+    }
+
+    // number of shapes composited into the deepest snapshot not including shape `idx`
+    fn base(&self, idx: usize) -> usize {
+        idx.checked_div(self.interval)
+            .unwrap_or(0)
+            .min(self.layers.len())
+    }
+
+    /// Renders `region` of `fb` for `shapes`, where only shape `idx` differs
+    /// from the shapes the cache was built for. The region is restored from
+    /// the nearest snapshot below `idx` and the remaining shapes drawn on top,
+    /// giving the same pixels as `fb.draw_shapes(shapes)`.
+    pub fn render_region<S: Shape>(
+        &self,
+        fb: &mut FrameBuffer,
+        shapes: &[S],
+        idx: usize,
+        region: Rect,
+    ) {
+        let base = self.base(idx);
+        if base == 0 {
+            fb.redraw_region(shapes, region);
+        } else {
+            fb.copy_region_from(&self.layers[base - 1], region);
+            fb.draw_region(&shapes[base * self.interval..], region);
+        }
+    }
+
+    /// As `FrameBuffer::update_error`, replaying only the shapes from the
+    /// nearest snapshot below `idx`, the one shape that changed.
+    pub fn update_error<S: Shape>(
+        &self,
+        fb: &mut FrameBuffer,
+        shapes: &[S],
+        idx: usize,
+        region: Rect,
+        target: &Target,
+        error: f64,
+    ) -> f64 {
+        let before = target.error_region(fb, region);
+        self.render_region(fb, shapes, idx, region);
+        error - before + target.error_region(fb, region)
+    }
+
+    /// Brings the snapshots up to date after shape `idx` changed, within
+    /// `region` (which must cover the change).
+    pub fn refresh<S: Shape>(&mut self, shapes: &[S], idx: usize, region: Rect) {
+        let first = self.base(idx);
+        for j in first..self.layers.len() {
+            let (below, above) = self.layers.split_at_mut(j);
+            let layer = &mut above[0];
+            match below.last() {
+                Some(prev) => layer.copy_region_from(prev, region),
+                None => layer.clear_region(region),
+            }
+            layer.draw_region(&shapes[j * self.interval..(j + 1) * self.interval], region);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetricKind, Primitive};
+    use mersenne_twister_rs::MersenneTwister64;
+    use rand_core::RngCore;
+
+    const WIDTH: u16 = 40;
+    const HEIGHT: u16 = 30;
+
+    fn target() -> Target {
+        let mut reference = FrameBuffer::new(WIDTH, HEIGHT);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % WIDTH as usize, i / WIDTH as usize);
+            px.copy_from_slice(&[(x * 6) as u8, (y * 8) as u8, ((x + y) * 3) as u8]);
+        }
+        Target::new(reference, MetricKind::Euclidean)
+    }
+
+    // Mutates random shapes as annealing does, keeping about half of the
+    // changes, and compares the incrementally maintained image and error
+    // with a full redraw after every step
+    fn check(interval: usize, antialias: bool, background: [u8; 3]) {
+        let target = target();
+        let mut rng = MersenneTwister64::new(interval as u64 + 1);
+        let mut shapes: Vec<Primitive> = (0..12)
+            .map(|_| Primitive::random(&mut rng, WIDTH, HEIGHT))
+            .collect();
+
+        let framebuffer = || {
+            let mut fb = FrameBuffer::new(WIDTH, HEIGHT);
+            fb.antialias = antialias;
+            fb.background = background;
+            fb
+        };
+        let mut fb = framebuffer();
+        fb.draw_shapes(&shapes);
+        let mut error = target.error(&fb);
+        let mut cache = LayerCache::new(&fb, interval);
+        cache.rebuild(&shapes);
+
+        for step in 0..300 {
+            let idx = (rng.next_u64() % shapes.len() as u64) as usize;
+            let original = shapes[idx].clone();
+            shapes[idx].mutate(&mut rng, WIDTH, HEIGHT);
+            if !antialias {
+                shapes[idx].snap_to_pixels();
+            }
+            let region = original.bounds().union(&shapes[idx].bounds());
+            let saved = fb.copy_region(region);
+            let new_error = cache.update_error(&mut fb, &shapes, idx, region, &target, error);
+
+            let mut full = framebuffer();
+            full.draw_shapes(&shapes);
+            assert_eq!(fb.pixels, full.pixels, "step {step}");
+            assert!(
+                (new_error - target.error(&full)).abs() < 1e-6,
+                "step {step}"
+            );
+
+            if rng.next_u64().is_multiple_of(2) {
+                error = new_error;
+                cache.refresh(&shapes, idx, region);
+            } else {
+                shapes[idx] = original;
+                fb.paste_region(region, &saved);
+            }
+        }
+
+        // the snapshots are as if built from scratch
+        let mut fresh = LayerCache::new(&fb, interval);
+        fresh.rebuild(&shapes);
+        assert_eq!(cache.layers.len(), fresh.layers.len());
+        for (a, b) in cache.layers.iter().zip(&fresh.layers) {
+            assert_eq!(a.pixels, b.pixels);
+        }
+    }
+
+    #[test]
+    fn without_snapshots() {
+        check(0, false, [0; 3]);
+        check(0, true, [0; 3]);
+    }
+
+    #[test]
+    fn snapshot_after_every_shape() {
+        check(1, false, [0; 3]);
+        check(1, true, [0; 3]);
+    }
+
+    #[test]
+    fn snapshot_every_few_shapes() {
+        check(5, false, [0; 3]);
+        check(5, true, [0; 3]);
+    }
+
+    #[test]
+    fn with_a_background() {
+        for interval in [0, 1, 5] {
+            check(interval, false, [10, 200, 30]);
+            check(interval, true, [10, 200, 30]);
+        }
+    }
+}
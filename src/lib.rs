@@ -4,11 +4,96 @@ use std::fs::File;
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
 
+use primitive::{Footprint, in_range};
+
+// `ALL`, `name`, `Display` and `FromStr` for an enum chosen by name, e.g. on
+// the command line; `what` it is goes in the error for an unknown name
+macro_rules! named_enum {
+    ($enum:ident, $what:literal, { $($variant:ident => $name:literal),+ $(,)? }) => {
+        impl $enum {
+            pub const ALL: [$enum; [$($name),+].len()] = [$($enum::$variant),+];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($enum::$variant => $name),+
+                }
+            }
+        }
+
+        impl std::fmt::Display for $enum {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl std::str::FromStr for $enum {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $enum::ALL.into_iter().find(|k| k.name() == s).ok_or_else(|| {
+                    let names: Vec<_> = $enum::ALL.iter().map(|k| k.name()).collect();
+                    format!(
+                        concat!("unknown ", $what, " '{}', expected one of: {}"),
+                        s,
+                        names.join(", ")
+                    )
+                })
+            }
+        }
+    };
+}
+
+pub mod animation;
+pub mod annealing;
+pub mod checkpoint;
+pub mod circle;
+pub mod cli;
+pub mod ellipse;
+pub mod fitness;
 pub mod frame_buffer;
-pub use frame_buffer::FrameBuffer;
+pub mod genetic;
+pub mod genome;
+pub mod greedy;
+pub mod interrupt;
+pub mod json;
+pub mod layer_cache;
+pub mod mask;
+pub mod morph;
+pub mod observer;
+pub mod optimizer;
+pub mod primitive;
+pub mod rectangle;
+pub mod schedule;
+pub mod shape;
+pub mod stop;
+pub mod svg;
+pub mod tempering;
+pub use animation::{Animation, AnimationRecorder, SvgAnimation, SvgAnimationRecorder};
+pub use annealing::{AnnealingConfig, SimulatedAnnealing};
+pub use checkpoint::{Checkpoint, CountingRng};
+pub use circle::Circle;
+pub use ellipse::Ellipse;
+pub use fitness::{Fitness, MetricKind, Target};
+pub use frame_buffer::{FrameBuffer, Rect};
+pub use genetic::{GeneticAlgorithm, GeneticConfig, Topology};
+pub use genome::Genome;
+pub use greedy::{Greedy, GreedyConfig};
+pub use json::Json;
+pub use layer_cache::LayerCache;
+pub use mask::Mask;
+pub use morph::Morph;
+pub use observer::{ConsoleLogger, CsvLogger, FrameSaver, JsonLinesLogger, Observer};
+pub use optimizer::Optimizer;
+pub use primitive::Primitive;
+pub use rectangle::{Rectangle, RotatedRectangle};
+pub use schedule::CoolingSchedule;
+pub use shape::{Shape, ShapeKind};
+pub use stop::{StopCriteria, StopReason};
+pub use svg::load_svg;
+pub use tempering::{ParallelTempering, TemperingConfig};
 
 #[derive(Clone)]
-struct Colour {
+pub struct Colour {
     r: u8,
     g: u8,
     b: u8,
@@ -52,11 +137,45 @@ impl Colour {
         let x = rand_between(rng, -idelta, idelta);
         self.alpha = (self.alpha as i64 + x).clamp(MINALPHA as i64, MAXALPHA as i64) as u8;
     }
+
+    /// Colour with opacity `alpha` in percent, clamped to the range used by the shapes
+    pub fn new(r: u8, g: u8, b: u8, alpha: u8) -> Self {
+        Self {
+            r,
+            g,
+            b,
+            alpha: alpha.clamp(MINALPHA, MAXALPHA),
+        }
+    }
+
+    /// Red, green, blue and opacity in percent
+    pub fn rgba(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.alpha]
+    }
+
+    /// Alpha as a blending factor in [0, 1]
+    pub fn opacity(&self) -> f32 {
+        self.alpha as f32 / 100.0
+    }
+
+    // inline style shared by all svg elements
+    fn svg_style(&self) -> String {
+        self.svg_style_with_opacity(self.opacity())
+    }
+
+    fn svg_style_with_opacity(&self, opacity: f32) -> String {
+        format!(
+            "fill:#{:02x}{:02x}{:02x};stroke:#000000;stroke-width:0;fill-opacity:{:.2};",
+            self.r, self.g, self.b, opacity
+        )
+    }
 }
 
 #[derive(Clone)]
 pub struct Triangle {
-    vertices: [(u16, u16); 3],
+    // sub-pixel positions when anti-aliased (see FrameBuffer::draw_triangle_aa),
+    // otherwise the optimisers keep them on whole pixels with snap_to_pixels
+    vertices: [(f32, f32); 3],
     colour: Colour,
 }
 
@@ -87,38 +206,75 @@ fn rand_between<R: RngCore + ?Sized>(rng: &mut R, min: i64, max: i64) -> i64 {
     min + r as i64
 }
 
+/// Seed for an independent random stream, e.g. one per thread and generation,
+/// derived from the user's `seed` (splitmix64 finaliser).
+pub fn stream_seed(seed: u64, a: u64, b: u64) -> u64 {
+    let mut z = seed
+        ^ a.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ b.wrapping_add(1).wrapping_mul(0xd1b5_4a32_d192_ed03);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// move `v` randomly by up to +-delta, keeping it inside [min, max]
+fn jitter<R: RngCore + ?Sized>(rng: &mut R, v: u16, delta: u16, min: i64, max: i64) -> u16 {
+    let delta_i = delta as i64;
+    (v as i64 + rand_between(rng, -delta_i, delta_i)).clamp(min, max.max(min)) as u16
+}
+
+// move `v` by a uniformly distributed amount in [-delta, delta], keeping it inside [min, max]
+fn jitter_f32<R: RngCore + ?Sized>(rng: &mut R, v: f32, delta: f32, min: f32, max: f32) -> f32 {
+    let u = rng.next_u64() as f64 / u64::MAX as f64;
+    (v + (2.0 * u as f32 - 1.0) * delta).clamp(min, max.max(min))
+}
+
 impl Triangle {
+    pub(crate) fn footprint(&self) -> Footprint {
+        let [(x1, y1), (x2, _), (x3, y3)] = self.vertices;
+        Footprint::from_bounds(x1.min(x2).min(x3), y1, x1.max(x2).max(x3), y3)
+    }
+
+    // isosceles triangle with its apex at the top centre of the footprint
+    pub(crate) fn from_footprint(fp: &Footprint, colour: Colour) -> Self {
+        let (x1, y1, x2, y2) = fp.bounds();
+        Triangle {
+            vertices: [(fp.centre.0, y1), (x1, y2), (x2, y2)],
+            colour,
+        }
+    }
+}
+
+impl Shape for Triangle {
     // random colour and random placement inside canvas
-    pub fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
         let (a, b, c, d) = rand_u16_x4(rng);
         let (e, f, _, _) = rand_u16_x4(rng);
         let mut t = Triangle {
             colour: Colour::random(rng),
             vertices: [
-                (a % width, b % height),
-                (c % width, d % height),
-                (e % width, f % height),
+                ((a % width) as f32, (b % height) as f32),
+                ((c % width) as f32, (d % height) as f32),
+                ((e % width) as f32, (f % height) as f32),
             ],
         };
         t.normalise(width, height);
         t
     }
 
-    // mutate: randomly move vertices
-    fn mutate_vertices<R: RngCore + ?Sized>(
+    // mutate: randomly move vertices, by fractions of a pixel too (see snap_to_pixels)
+    fn mutate_geometry<R: RngCore + ?Sized>(
         &mut self,
         rng: &mut R,
         width: u16,
         height: u16,
         delta: u16,
     ) {
-        let delta_i = delta as i64;
-        for i in 0..3 {
-            let dx = rand_between(rng, -delta_i, delta_i);
-            let dy = rand_between(rng, -delta_i, delta_i);
-            self.vertices[i].0 = (self.vertices[i].0 as i64 + dx).clamp(0, width as i64 - 1) as u16;
-            self.vertices[i].1 =
-                (self.vertices[i].1 as i64 + dy).clamp(0, height as i64 - 1) as u16;
+        let max_x = width.saturating_sub(1) as f32;
+        let max_y = height.saturating_sub(1) as f32;
+        for vertex in &mut self.vertices {
+            vertex.0 = jitter_f32(rng, vertex.0, delta as f32, 0.0, max_x);
+            vertex.1 = jitter_f32(rng, vertex.1, delta as f32, 0.0, max_y);
         }
         self.normalise(width, height);
     }
@@ -130,44 +286,104 @@ impl Triangle {
     //This function normalizes it turning an invalid triangle into a valid one. */
     fn normalise(&mut self, width: u16, height: u16) {
         // Sort vertices by Y-coordinate (Ascending) to ensure y1 <= y2 <= y3.
-        self.vertices.sort_by(|a, b| a.1.cmp(&b.1));
+        self.vertices.sort_by(|a, b| a.1.total_cmp(&b.1));
 
         // Clamp coordinates to fit inside the canvas
-        let max_x = width.saturating_sub(1);
-        let max_y = height.saturating_sub(1);
+        let max_x = width.saturating_sub(1) as f32;
+        let max_y = height.saturating_sub(1) as f32;
         for vertex in &mut self.vertices {
-            if vertex.0 > max_x {
-                vertex.0 = max_x;
-            }
-            if vertex.1 > max_y {
-                vertex.1 = max_y;
-            }
+            vertex.0 = vertex.0.clamp(0.0, max_x);
+            vertex.1 = vertex.1.clamp(0.0, max_y);
         }
     }
 
-    // Apply a random mutation
-    pub fn mutate<R: RngCore + ?Sized>(&mut self, rng: &mut R, width: u16, height: u16) {
-        match rng.next_u64() % 10 {
-            // Changed from 6
-            0 => *self = Triangle::random(rng, width, height),
-            1 | 2 => self.mutate_vertices(rng, width, height, 3), // Small vertex moves
-            3 | 4 => self.mutate_vertices(rng, width, height, 10), // Medium vertex moves
-            5 | 6 => self.colour.mutate_colour(rng, 10),
-            7 | 8 => self.colour.mutate_colour(rng, 30),
-            _ => self.colour.mutate_alpha(rng, 10),
+    fn snap_to_pixels(&mut self) {
+        // rounding keeps the vertices on the canvas and ordered by y
+        for vertex in &mut self.vertices {
+            *vertex = (vertex.0.round(), vertex.1.round());
+        }
+    }
+
+    fn colour(&self) -> &Colour {
+        &self.colour
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        &mut self.colour
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        fb.draw_triangle(self);
+    }
+
+    fn bounds(&self) -> Rect {
+        let [(x1, y1), (x2, _), (x3, y3)] = self.vertices;
+        Rect::around(x1.min(x2).min(x3), y1, x1.max(x2).max(x3), y3)
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        // to a hundredth of a pixel, which is plenty even when anti-aliased
+        let [(x1, y1), (x2, y2), (x3, y3)] = self
+            .vertices
+            .map(|(x, y)| ((x * 100.0).round() / 100.0, (y * 100.0).round() / 100.0));
+        writeln!(
+            w,
+            r#"<polygon points="{},{} {},{} {},{}" style="{}"/>"#,
+            x1,
+            y1,
+            x2,
+            y2,
+            x3,
+            y3,
+            self.colour.svg_style()
+        )
+    }
+
+    fn kind(&self) -> ShapeKind {
+        ShapeKind::Triangle
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        self.vertices.iter().flat_map(|&(x, y)| [x, y]).collect()
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        match (kind, geometry) {
+            (ShapeKind::Triangle, &[x1, y1, x2, y2, x3, y3]) if in_range(geometry) => {
+                Some(Triangle {
+                    vertices: [(x1, y1), (x2, y2), (x3, y3)],
+                    colour,
+                })
+            }
+            _ => None,
         }
     }
 }
 
-pub fn save_svg<P: AsRef<Path>>(
+pub fn save_svg<P: AsRef<Path>, S: Shape>(
     filename: P,
-    triangles: &[Triangle],
+    shapes: &[S],
     width: u16,
     height: u16,
 ) -> io::Result<()> {
     let file = File::create(filename)?;
     let mut w = BufWriter::new(file);
 
+    write_svg_header(&mut w, width, height)?;
+
+    // Shapes
+    for s in shapes {
+        s.write_svg(&mut w)?;
+    }
+
+    writeln!(w, "</svg>")?;
+    w.flush()?;
+    Ok(())
+}
+
+/// The start of the SVG files written by `save_svg`, up to and including the
+/// background; the shapes and the closing `</svg>` follow
+pub fn write_svg_header<W: Write>(w: &mut W, width: u16, height: u16) -> io::Result<()> {
     // Header
     writeln!(
         w,
@@ -182,21 +398,102 @@ pub fn save_svg<P: AsRef<Path>>(
         width - 1,
         height - 1,
         height - 1
-    )?;
+    )
+}
 
-    // Triangles
-    for t in triangles {
-        let [(x1, y1), (x2, y2), (x3, y3)] = t.vertices;
-        let c = &t.colour;
-        let opacity = c.alpha as f32 / 100.0;
-        writeln!(
-            w,
-            r#"<polygon points="{},{} {},{} {},{}" style="fill:#{:02x}{:02x}{:02x};stroke:#000000;stroke-width:0;fill-opacity:{:.2};"/>"#,
-            x1, y1, x2, y2, x3, y3, c.r, c.g, c.b, opacity
-        )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(geometry: &[f32]) -> Triangle {
+        Triangle::from_geometry(ShapeKind::Triangle, geometry, Colour::new(1, 2, 3, 50)).unwrap()
     }
 
-    writeln!(w, "</svg>")?;
-    w.flush()?;
-    Ok(())
+    #[test]
+    fn triangle_geometry_round_trips() {
+        let t = triangle(&[1.5, 2.0, 8.0, 3.25, 4.0, 9.0]);
+        let copy = Triangle::from_geometry(t.kind(), &t.geometry(), t.colour().clone()).unwrap();
+        assert_eq!(copy.geometry(), vec![1.5, 2.0, 8.0, 3.25, 4.0, 9.0]);
+    }
+
+    #[test]
+    fn snap_to_pixels_rounds_vertices() {
+        let mut t = triangle(&[1.4, 2.0, 8.6, 3.5, 4.0, 9.2]);
+        t.snap_to_pixels();
+        assert_eq!(t.geometry(), vec![1.0, 2.0, 9.0, 4.0, 4.0, 9.0]);
+    }
+
+    #[test]
+    fn mutations_stay_on_whole_pixels_when_snapped() {
+        let mut rng = mersenne_twister_rs::MersenneTwister64::new(3);
+        let mut t = Triangle::random(&mut rng, 50, 40);
+        for _ in 0..500 {
+            t.mutate(&mut rng, 50, 40);
+            t.snap_to_pixels();
+            assert!(t.geometry().iter().all(|v| v.fract() == 0.0));
+        }
+    }
+
+    #[test]
+    fn triangle_svg_is_rounded() {
+        let t = triangle(&[74.96792, 18.949408, 10.0, 20.0, 3.0, 30.0]);
+        let mut out = Vec::new();
+        t.write_svg(&mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(
+            svg.starts_with(r#"<polygon points="74.97,18.95 10,20 3,30" "#),
+            "{svg}"
+        );
+    }
+
+    fn check_names<T>(all: &[T], names: &[&str], what: &str)
+    where
+        T: Copy + PartialEq + fmt::Debug + fmt::Display + std::str::FromStr<Err = String>,
+    {
+        let shown: Vec<String> = all.iter().map(T::to_string).collect();
+        assert_eq!(shown, names);
+        for &k in all {
+            assert_eq!(k.to_string().parse::<T>(), Ok(k));
+        }
+        assert_eq!(
+            "nonsense".parse::<T>(),
+            Err(format!(
+                "unknown {what} 'nonsense', expected one of: {}",
+                names.join(", ")
+            ))
+        );
+    }
+
+    #[test]
+    fn named_enums_parse_back() {
+        check_names(
+            &ShapeKind::ALL,
+            &[
+                "triangle",
+                "circle",
+                "ellipse",
+                "rectangle",
+                "rotated-rectangle",
+                "mixed",
+            ],
+            "shape",
+        );
+        check_names(
+            &MetricKind::ALL,
+            &["mse", "euclidean", "ssim", "ciede2000"],
+            "metric",
+        );
+        check_names(
+            &CoolingSchedule::ALL,
+            &[
+                "exponential",
+                "linear",
+                "logarithmic",
+                "lundy-mees",
+                "adaptive",
+            ],
+            "schedule",
+        );
+        check_names(&Topology::ALL, &["ring", "full"], "topology");
+    }
 }
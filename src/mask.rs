@@ -0,0 +1,181 @@
+use crate::FrameBuffer;
+
+/// Per-pixel importance weights for the fitness metrics.
+///
+/// Weights are scaled to average 1, so a masked error stays comparable with
+/// an unmasked one: important pixels count more, the rest count less.
+pub struct Mask {
+    weights: Vec<f32>,
+}
+
+impl Mask {
+    /// Weights from the brightness of a grayscale image (white = important),
+    /// resized to `width` x `height` if necessary.
+    pub fn from_image(img: &image::DynamicImage, width: u16, height: u16) -> Self {
+        let mut luma = img.to_luma8();
+        if luma.dimensions() != (width as u32, height as u32) {
+            luma = image::imageops::resize(
+                &luma,
+                width as u32,
+                height as u32,
+                image::imageops::FilterType::Triangle,
+            );
+        }
+        Self::normalised(luma.pixels().map(|p| p.0[0] as f32 / 255.0).collect())
+    }
+
+    /// Weights derived from the reference itself: pixels near strong edges
+    /// (Sobel gradient of the luminance, blurred so whole features such as
+    /// eyes stand out) get up to five times the weight of flat background.
+    pub fn from_edges(reference: &FrameBuffer) -> Self {
+        let (w, h) = (reference.width as usize, reference.height as usize);
+        let luma: Vec<f32> = reference
+            .pixels
+            .chunks(3)
+            .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+            .collect();
+        let at = |x: usize, y: usize| luma[y.min(h - 1) * w + x.min(w - 1)];
+
+        let mut edges = vec![0.0; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let (xl, xr) = (x.saturating_sub(1), x + 1);
+                let (yu, yd) = (y.saturating_sub(1), y + 1);
+                let gx = at(xr, yu) + 2.0 * at(xr, y) + at(xr, yd)
+                    - at(xl, yu)
+                    - 2.0 * at(xl, y)
+                    - at(xl, yd);
+                let gy = at(xl, yd) + 2.0 * at(x, yd) + at(xr, yd)
+                    - at(xl, yu)
+                    - 2.0 * at(x, yu)
+                    - at(xr, yu);
+                edges[y * w + x] = (gx * gx + gy * gy).sqrt();
+            }
+        }
+
+        let radius = (w.max(h) / 32).max(1);
+        let blurred = box_blur(&box_blur(&edges, w, h, radius), w, h, radius);
+        let max = blurred
+            .iter()
+            .cloned()
+            .fold(0.0, f32::max)
+            .max(f32::EPSILON);
+        Self::normalised(blurred.iter().map(|v| 0.2 + 0.8 * v / max).collect())
+    }
+
+    fn normalised(mut weights: Vec<f32>) -> Self {
+        let mean = weights.iter().map(|&v| v as f64).sum::<f64>() / weights.len().max(1) as f64;
+        if mean > 0.0 {
+            weights
+                .iter_mut()
+                .for_each(|v| *v = (*v as f64 / mean) as f32);
+        } else {
+            weights.fill(1.0);
+        }
+        Self { weights }
+    }
+
+    /// Weight of pixel number `idx` (row major)
+    pub fn weight(&self, idx: usize) -> f64 {
+        self.weights[idx] as f64
+    }
+}
+
+// separable box blur with the given radius, clamping at the borders
+fn box_blur(v: &[f32], w: usize, h: usize, radius: usize) -> Vec<f32> {
+    let pass = |src: &[f32], len: usize, lines: usize, idx: &dyn Fn(usize, usize) -> usize| {
+        let mut out = vec![0.0; src.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let lo = i.saturating_sub(radius);
+                let hi = (i + radius).min(len - 1);
+                let sum: f32 = (lo..=hi).map(|j| src[idx(line, j)]).sum();
+                out[idx(line, i)] = sum / (hi - lo + 1) as f32;
+            }
+        }
+        out
+    };
+    let horizontal = pass(v, w, h, &|y, x| y * w + x);
+    pass(&horizontal, h, w, &|x, y| y * w + x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetricKind, Target};
+
+    fn mean(mask: &Mask) -> f64 {
+        (0..mask.weights.len()).map(|i| mask.weight(i)).sum::<f64>() / mask.weights.len() as f64
+    }
+
+    #[test]
+    fn weights_average_one() {
+        let mask = Mask::normalised(vec![1.0, 2.0, 3.0, 2.0]);
+        assert_eq!(mask.weights, [0.5, 1.0, 1.5, 1.0]);
+        // an all black mask weights every pixel the same
+        assert_eq!(Mask::normalised(vec![0.0; 3]).weights, [1.0; 3]);
+
+        let img = image::GrayImage::from_fn(10, 6, |x, y| image::Luma([(x * 20 + y * 5) as u8]));
+        let img = image::DynamicImage::ImageLuma8(img);
+        for (w, h) in [(10, 6), (25, 13)] {
+            let mask = Mask::from_image(&img, w, h);
+            assert_eq!(mask.weights.len(), w as usize * h as usize);
+            assert!((mean(&mask) - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn edges_weigh_more_than_flat_regions() {
+        // black on the left, white from x = 16 on
+        let mut reference = FrameBuffer::new(64, 32);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            if i % 64 >= 16 {
+                px.fill(255);
+            }
+        }
+        let mask = Mask::from_edges(&reference);
+        assert!((mean(&mask) - 1.0).abs() < 1e-6);
+        let at = |x: usize, y: usize| mask.weight(y * 64 + x);
+        for y in [0, 10, 31] {
+            assert!(at(16, y) > 2.0 * at(50, y), "row {y}");
+            assert!(at(15, y) > 2.0 * at(2, y), "row {y}");
+        }
+        // a flat image has no edges to weight
+        let flat = Mask::from_edges(&FrameBuffer::new(8, 8));
+        assert!(flat.weights.iter().all(|&v| v == 1.0));
+    }
+
+    #[test]
+    fn masked_error_is_weighted_per_pixel() {
+        let reference = || FrameBuffer::new(4, 2);
+        // pixels 0 and 5 are equally wrong
+        let mut image = reference();
+        image.pixels[..3].fill(40);
+        image.pixels[15..18].fill(40);
+        let weights = vec![1.0, 1.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0];
+
+        for metric in [
+            MetricKind::Mse,
+            MetricKind::Euclidean,
+            MetricKind::Ciede2000,
+        ] {
+            let plain = Target::new(reference(), metric).error(&image);
+            assert!(plain > 0.0);
+            let mask = Mask::normalised(weights.clone());
+            let (w0, w5) = (mask.weight(0), mask.weight(5));
+            let masked = Target::new(reference(), metric)
+                .with_mask(Some(mask))
+                .error(&image);
+            let expected = plain / 2.0 * (w0 + w5);
+            assert!((masked - expected).abs() < 1e-6 * expected, "{metric}");
+        }
+
+        for metric in MetricKind::ALL {
+            let plain = Target::new(reference(), metric).error(&image);
+            let uniform = Target::new(reference(), metric)
+                .with_mask(Some(Mask::normalised(vec![0.3; 8])))
+                .error(&image);
+            assert!((uniform - plain).abs() <= 1e-6 * plain, "{metric}");
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Morphing from one solution to another, e.g. for title sequences.
+//!
+//! The shapes of the two solutions are paired up, and each frame draws
+//! every pair part of the way from one shape to the other: geometry and
+//! colour are interpolated linearly (rotations the shorter way round), and
+//! the painting order moves from that
+//! of the first solution to that of the second. Shapes without a partner
+//! fade in or out where they are.
+
+use crate::{Colour, FrameBuffer, Genome, Shape, ShapeKind};
+
+/// Two solutions with their shapes matched up for morphing
+pub struct Morph<S> {
+    /// The canvas of the first solution, which the second is fitted into
+    pub width: u16,
+    pub height: u16,
+    pairs: Vec<Pair<S>>,
+}
+
+// Shapes of the same kind to interpolate between, and where they come in
+// the painting order of each solution, from 0 to 1
+struct Pair<S> {
+    from: S,
+    to: S,
+    order: (f32, f32),
+}
+
+impl<S: Shape> Morph<S> {
+    /// Match the shapes of `from` and `to`. Shapes of the same kind are
+    /// paired greedily, the closest in position and colour first; the rest
+    /// are paired with a transparent copy of themselves. `to` is scaled to
+    /// fit the canvas of `from`, centred and keeping its aspect ratio.
+    pub fn new(from: &Genome<S>, to: &Genome<S>) -> Self {
+        let (width, height) = (from.width, from.height);
+        let scale = f32::min(
+            width as f32 / to.width as f32,
+            height as f32 / to.height as f32,
+        );
+        let offset = (
+            (width as f32 - to.width as f32 * scale) / 2.0,
+            (height as f32 - to.height as f32 * scale) / 2.0,
+        );
+        let fitted: Vec<S> = to
+            .shapes
+            .iter()
+            .map(|s| fit(s, scale, offset, width, height))
+            .collect();
+
+        // position in the painting order, from 0 to 1
+        let rank = |i: usize, n: usize| i as f32 / n.saturating_sub(1).max(1) as f32;
+        let diagonal = (width as f32).hypot(height as f32);
+
+        let mut candidates = Vec::new();
+        for (i, a) in from.shapes.iter().enumerate() {
+            for (j, b) in fitted.iter().enumerate() {
+                if a.kind() == b.kind() {
+                    let (cost, vertices) = closest(a, b, diagonal);
+                    candidates.push((cost, i, j, vertices));
+                }
+            }
+        }
+        candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        let mut from_used = vec![false; from.shapes.len()];
+        let mut to_used = vec![false; fitted.len()];
+        let mut pairs = Vec::new();
+        for (_, i, j, vertices) in candidates {
+            if !from_used[i] && !to_used[j] {
+                from_used[i] = true;
+                to_used[j] = true;
+                pairs.push(Pair {
+                    from: from.shapes[i].clone(),
+                    to: reorder(&fitted[j], vertices),
+                    order: (rank(i, from.shapes.len()), rank(j, fitted.len())),
+                });
+            }
+        }
+        for (i, a) in from.shapes.iter().enumerate() {
+            if !from_used[i] {
+                let r = rank(i, from.shapes.len());
+                pairs.push(Pair {
+                    from: a.clone(),
+                    to: transparent(a),
+                    order: (r, r),
+                });
+            }
+        }
+        for (j, b) in fitted.iter().enumerate() {
+            if !to_used[j] {
+                let r = rank(j, fitted.len());
+                pairs.push(Pair {
+                    from: transparent(b),
+                    to: b.clone(),
+                    order: (r, r),
+                });
+            }
+        }
+        pairs.sort_by(|x, y| x.order.0.total_cmp(&y.order.0));
+
+        Self {
+            width,
+            height,
+            pairs,
+        }
+    }
+
+    /// Number of shapes drawn in each frame
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// The shapes `t` of the way from the first solution (0) to the second
+    /// (1), in painting order
+    pub fn shapes_at(&self, t: f32) -> Vec<S> {
+        let t = t.clamp(0.0, 1.0);
+        let mut order: Vec<(f32, usize)> = self
+            .pairs
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (lerp(p.order.0, p.order.1, t), i))
+            .collect();
+        order.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        order
+            .into_iter()
+            .map(|(_, i)| interpolate(&self.pairs[i], t, self.width, self.height))
+            .collect()
+    }
+
+    /// Draw the frame `t` of the way through the morph into `fb`, which
+    /// should have the size of the canvas times `fb.scale`
+    pub fn render(&self, fb: &mut FrameBuffer, t: f32) {
+        fb.draw_shapes(&self.shapes_at(t));
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// `t` of the way from angle `a` to `b` in degrees, for shapes that look the
+// same every 180 degrees, turning the shorter way round
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    a + ((b - a + 90.0).rem_euclid(180.0) - 90.0) * t
+}
+
+fn interpolate<S: Shape>(pair: &Pair<S>, t: f32, width: u16, height: u16) -> S {
+    let (a, b) = (pair.from.geometry(), pair.to.geometry());
+    let angle = pair.from.kind() == ShapeKind::RotatedRectangle;
+    let geometry: Vec<f32> = a
+        .iter()
+        .zip(&b)
+        .enumerate()
+        .map(|(i, (&a, &b))| {
+            if angle && i == 4 {
+                lerp_angle(a, b, t)
+            } else {
+                lerp(a, b, t)
+            }
+        })
+        .collect();
+    let (ca, cb) = (pair.from.colour().rgba(), pair.to.colour().rgba());
+    let [r, g, b, alpha] =
+        std::array::from_fn(|i| lerp(ca[i] as f32, cb[i] as f32, t).round() as u8);
+    // not Colour::new, so that a fading shape can become fully transparent
+    let colour = Colour { r, g, b, alpha };
+    let mut shape = S::from_geometry(pair.from.kind(), &geometry, colour)
+        .expect("both shapes of a pair have the same kind");
+    shape.normalise(width, height);
+    shape
+}
+
+// `shape` with no opacity at all, to fade in or out
+fn transparent<S: Shape>(shape: &S) -> S {
+    let mut shape = shape.clone();
+    shape.colour_mut().alpha = 0;
+    shape
+}
+
+// Scale `shape` by `scale` about the origin then move it by `offset`
+fn fit<S: Shape>(shape: &S, scale: f32, offset: (f32, f32), width: u16, height: u16) -> S {
+    let mut geometry = shape.geometry();
+    let (x, y) = offset;
+    // which of the numbers are x and y coordinates rather than sizes or angles
+    let positions: &[usize] = match shape.kind() {
+        ShapeKind::Triangle => &[0, 1, 2, 3, 4, 5],
+        ShapeKind::Rectangle => &[0, 1, 2, 3],
+        _ => &[0, 1],
+    };
+    let angle = shape.kind() == ShapeKind::RotatedRectangle;
+    for (i, v) in geometry.iter_mut().enumerate() {
+        if positions.contains(&i) {
+            *v = *v * scale + if i % 2 == 0 { x } else { y };
+        } else if !(angle && i == 4) {
+            // no smaller than normalise would make it
+            *v = (*v * scale).max(1.0);
+        }
+    }
+    let mut fitted = S::from_geometry(shape.kind(), &geometry, shape.colour().clone())
+        .expect("geometry of the same kind and length");
+    fitted.normalise(width, height);
+    fitted
+}
+
+const VERTEX_ORDERS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+// the vertices of triangle geometry `g` in the given order
+fn vertices_in_order(g: &[f32], order: [usize; 3]) -> Vec<f32> {
+    order
+        .iter()
+        .flat_map(|&v| [g[2 * v], g[2 * v + 1]])
+        .collect()
+}
+
+// `shape` with its vertices in `order` if it is a triangle
+fn reorder<S: Shape>(shape: &S, order: [usize; 3]) -> S {
+    let geometry = shape.geometry();
+    if shape.kind() != ShapeKind::Triangle || geometry.len() != 6 {
+        return shape.clone();
+    }
+    S::from_geometry(
+        shape.kind(),
+        &vertices_in_order(&geometry, order),
+        shape.colour().clone(),
+    )
+    .expect("geometry of the same kind and length")
+}
+
+// How far apart `a` and `b` are, with the vertices of `b` in the order
+// closest to those of `a` if they are triangles, and that order
+fn closest<S: Shape>(a: &S, b: &S, diagonal: f32) -> (f32, [usize; 3]) {
+    let (ga, gb) = (a.geometry(), b.geometry());
+    let distance = |g: &[f32]| -> f32 {
+        let sum: f32 = ga.iter().zip(g).map(|(a, b)| (a - b) * (a - b)).sum();
+        sum / ga.len().max(1) as f32 / (diagonal * diagonal)
+    };
+
+    let mut order = VERTEX_ORDERS[0];
+    let mut best = distance(&gb);
+    if a.kind() == ShapeKind::Triangle && gb.len() == 6 {
+        for &o in &VERTEX_ORDERS[1..] {
+            let d = distance(&vertices_in_order(&gb, o));
+            if d < best {
+                best = d;
+                order = o;
+            }
+        }
+    }
+
+    let (ca, cb) = (a.colour().rgba(), b.colour().rgba());
+    let colour: f32 = (0..3)
+        .map(|i| (ca[i] as f32 - cb[i] as f32) / 255.0)
+        .chain([(ca[3] as f32 - cb[3] as f32) / 100.0])
+        .map(|d| d * d)
+        .sum::<f32>()
+        / 4.0;
+
+    (best + colour, order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Primitive;
+
+    fn genome(size: u16, kind: ShapeKind, geometry: &[f32]) -> Genome<Primitive> {
+        let shape = Primitive::from_geometry(kind, geometry, Colour::new(10, 20, 30, 50)).unwrap();
+        Genome::new(size, size, vec![shape])
+    }
+
+    fn angle_at(morph: &Morph<Primitive>, t: f32) -> f32 {
+        morph.shapes_at(t)[0].geometry()[4]
+    }
+
+    #[test]
+    fn rotation_takes_the_shorter_way_across_180() {
+        let from = genome(
+            50,
+            ShapeKind::RotatedRectangle,
+            &[20.0, 20.0, 5.0, 3.0, 170.0],
+        );
+        let to = genome(
+            50,
+            ShapeKind::RotatedRectangle,
+            &[20.0, 20.0, 5.0, 3.0, 10.0],
+        );
+        let morph = Morph::new(&from, &to);
+        assert_eq!(morph.len(), 1);
+        assert_eq!(angle_at(&morph, 0.0), 170.0);
+        assert_eq!(angle_at(&morph, 0.25), 175.0);
+        assert_eq!(angle_at(&morph, 0.5), 0.0);
+        assert_eq!(angle_at(&morph, 0.75), 5.0);
+        assert_eq!(angle_at(&morph, 1.0), 10.0);
+
+        // and back again
+        let morph = Morph::new(&to, &from);
+        assert_eq!(angle_at(&morph, 0.25), 5.0);
+        assert_eq!(angle_at(&morph, 0.75), 175.0);
+    }
+
+    #[test]
+    fn rotation_within_the_half_turn_is_linear() {
+        let from = genome(
+            50,
+            ShapeKind::RotatedRectangle,
+            &[20.0, 20.0, 5.0, 3.0, 30.0],
+        );
+        let to = genome(
+            50,
+            ShapeKind::RotatedRectangle,
+            &[20.0, 20.0, 5.0, 3.0, 110.0],
+        );
+        let morph = Morph::new(&from, &to);
+        assert_eq!(angle_at(&morph, 0.5), 70.0);
+    }
+
+    #[test]
+    fn shapes_shrunk_to_fit_stay_valid() {
+        let from = genome(10, ShapeKind::Circle, &[5.0, 5.0, 2.0]);
+        let to = genome(40, ShapeKind::Circle, &[20.0, 20.0, 1.0]);
+        let morph = Morph::new(&from, &to);
+        assert_eq!(morph.shapes_at(1.0)[0].geometry(), vec![5.0, 5.0, 1.0]);
+    }
+}
@@ -0,0 +1,411 @@
+//! Hooks for following an optimisation: logging, saving frames, metrics.
+//!
+//! Observers are attached to an optimiser with `Optimizer::observe` and
+//! called from `step` and `finish`. The generation an event reports is the
+//! one just run, counting from 0 - the first of them for an optimiser that
+//! runs several per step.
+
+use crate::{FrameBuffer, Json, Optimizer, Shape};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+pub trait Observer<S: Shape> {
+    /// After every generation
+    fn on_generation(&mut self, _optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// After a generation that improved the best solution, before
+    /// `on_generation`
+    fn on_improvement(&mut self, _optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// After a shape was added to the current solution
+    fn on_shape_added(&mut self, _optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// When the run is over
+    fn on_finish(&mut self, _optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Call `event` on every observer of `optimizer`
+pub(crate) fn notify<S, O, F>(optimizer: &mut O, event: F) -> io::Result<()>
+where
+    S: Shape,
+    O: Optimizer<S>,
+    F: Fn(&mut dyn Observer<S>, &dyn Optimizer<S>) -> io::Result<()>,
+{
+    // the observers are moved out so that they can see the optimiser
+    let mut observers = std::mem::take(optimizer.observers());
+    let result = observers
+        .iter_mut()
+        .try_for_each(|o| event(o.as_mut(), &*optimizer));
+    *optimizer.observers() = observers;
+    result
+}
+
+// the generation just run, see the module documentation
+pub(crate) fn last_generation<S: Shape>(optimizer: &dyn Optimizer<S>) -> u64 {
+    optimizer
+        .generation()
+        .saturating_sub(optimizer.generations_per_step())
+}
+
+// whether an event for `generation` is due every `interval` generations
+fn due(generation: u64, interval: u64) -> bool {
+    interval > 0 && generation.is_multiple_of(interval)
+}
+
+/// Prints the status of the optimiser every `interval` generations
+pub struct ConsoleLogger {
+    interval: u64,
+    generations: u64,
+}
+
+impl ConsoleLogger {
+    /// `generations` is the length of the run, shown with the progress
+    pub fn new(interval: u64, generations: u64) -> Self {
+        Self {
+            interval,
+            generations,
+        }
+    }
+}
+
+impl<S: Shape> Observer<S> for ConsoleLogger {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let generation = last_generation(optimizer);
+        if due(generation, self.interval) {
+            println!(
+                "Gen {generation}/{}: {}",
+                self.generations,
+                optimizer.status()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Renders the best solution into `dir/frame_NNNNNN.png` every `interval`
+/// generations, for making an animation of the run
+pub struct FrameSaver {
+    dir: PathBuf,
+    interval: u64,
+    fb: FrameBuffer,
+}
+
+impl FrameSaver {
+    /// Frames are rendered with the size and settings of `fb`; `dir` is
+    /// created if needed
+    pub fn new<P: Into<PathBuf>>(dir: P, interval: u64, fb: FrameBuffer) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, interval, fb })
+    }
+}
+
+impl<S: Shape> Observer<S> for FrameSaver {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let generation = last_generation(optimizer);
+        if due(generation, self.interval) {
+            self.fb.draw_shapes(optimizer.best());
+            let name = format!("frame_{:06}.png", generation / self.interval);
+            self.fb
+                .save_png(self.dir.join(name))
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the fitness every `interval` generations as CSV, with the columns
+/// `generation,seconds,fitness,best_fitness,shapes`
+pub struct CsvLogger<W: Write> {
+    out: W,
+    interval: u64,
+    start: Instant,
+}
+
+impl<W: Write> CsvLogger<W> {
+    pub fn new(mut out: W, interval: u64) -> io::Result<Self> {
+        writeln!(out, "generation,seconds,fitness,best_fitness,shapes")?;
+        Ok(Self {
+            out,
+            interval,
+            start: Instant::now(),
+        })
+    }
+}
+
+impl<S: Shape, W: Write> Observer<S> for CsvLogger<W> {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let generation = last_generation(optimizer);
+        if due(generation, self.interval) {
+            let target = optimizer.target();
+            writeln!(
+                self.out,
+                "{generation},{:.3},{},{},{}",
+                self.start.elapsed().as_secs_f64(),
+                target.normalise(optimizer.fitness()),
+                target.normalise(optimizer.best_fitness()),
+                optimizer.current().len()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn on_finish(&mut self, _optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes one JSON object per line for each event: every improvement and
+/// added shape, the generation every `interval` generations, and the end of
+/// the run. Each has an `"event"` field naming it.
+pub struct JsonLinesLogger<W: Write> {
+    out: W,
+    interval: u64,
+    start: Instant,
+}
+
+impl<W: Write> JsonLinesLogger<W> {
+    pub fn new(out: W, interval: u64) -> Self {
+        Self {
+            out,
+            interval,
+            start: Instant::now(),
+        }
+    }
+
+    fn write<S: Shape>(&mut self, event: &str, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        let target = optimizer.target();
+        let json = Json::object(vec![
+            ("event", Json::String(event.to_string())),
+            ("generation", Json::number(last_generation(optimizer))),
+            ("seconds", Json::number(self.start.elapsed().as_secs_f64())),
+            (
+                "fitness",
+                Json::number(target.normalise(optimizer.fitness())),
+            ),
+            (
+                "best_fitness",
+                Json::number(target.normalise(optimizer.best_fitness())),
+            ),
+            ("shapes", Json::number(optimizer.current().len())),
+        ]);
+        writeln!(self.out, "{json}")
+    }
+}
+
+impl<S: Shape, W: Write> Observer<S> for JsonLinesLogger<W> {
+    fn on_generation(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        if due(last_generation(optimizer), self.interval) {
+            self.write("generation", optimizer)?;
+        }
+        Ok(())
+    }
+
+    fn on_improvement(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        self.write("improvement", optimizer)
+    }
+
+    fn on_shape_added(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        self.write("shape_added", optimizer)
+    }
+
+    fn on_finish(&mut self, optimizer: &dyn Optimizer<S>) -> io::Result<()> {
+        self.write("finish", optimizer)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnnealingConfig, MetricKind, SimulatedAnnealing, Target, Triangle};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A buffer that can still be read once an observer has taken it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Shared {
+        fn lines(&self) -> Vec<String> {
+            let text = String::from_utf8(self.0.borrow().clone()).unwrap();
+            text.lines().map(str::to_string).collect()
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // An event with the generation it reports and the best fitness then
+    type Event = (&'static str, u64, f64);
+
+    // Every event
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl Recorder {
+        fn record(&self, event: &'static str, optimizer: &dyn Optimizer<Triangle>) {
+            let generation = last_generation(optimizer);
+            let best = optimizer.best_fitness();
+            self.0.borrow_mut().push((event, generation, best));
+        }
+    }
+
+    impl Observer<Triangle> for Recorder {
+        fn on_generation(&mut self, optimizer: &dyn Optimizer<Triangle>) -> io::Result<()> {
+            self.record("generation", optimizer);
+            Ok(())
+        }
+
+        fn on_improvement(&mut self, optimizer: &dyn Optimizer<Triangle>) -> io::Result<()> {
+            self.record("improvement", optimizer);
+            Ok(())
+        }
+
+        fn on_shape_added(&mut self, optimizer: &dyn Optimizer<Triangle>) -> io::Result<()> {
+            self.record("shape_added", optimizer);
+            Ok(())
+        }
+
+        fn on_finish(&mut self, optimizer: &dyn Optimizer<Triangle>) -> io::Result<()> {
+            self.record("finish", optimizer);
+            Ok(())
+        }
+    }
+
+    fn annealing() -> SimulatedAnnealing<Triangle> {
+        let mut reference = FrameBuffer::new(24, 16);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % 24, i / 24);
+            px.copy_from_slice(&[(x * 10) as u8, (y * 15) as u8, 128]);
+        }
+        let config = AnnealingConfig::default()
+            .num_shapes(4)
+            .add_interval(10)
+            .seed(5);
+        SimulatedAnnealing::new(Target::new(reference, MetricKind::Euclidean), config)
+    }
+
+    const GENERATIONS: u64 = 40;
+
+    // Run `annealing()` with `observer` and a recorder, returning the events
+    fn run(observer: impl Observer<Triangle> + 'static) -> Vec<Event> {
+        let mut annealing = annealing();
+        let recorder = Recorder::default();
+        annealing.observe(observer);
+        annealing.observe(recorder.clone());
+        annealing.run(GENERATIONS).unwrap();
+        annealing.finish().unwrap();
+        recorder.0.take()
+    }
+
+    #[test]
+    fn improvements_are_reported_when_the_best_drops() {
+        let initial = annealing().best_fitness();
+        let events = run(Recorder::default());
+        let generations: Vec<_> = events.iter().filter(|e| e.0 == "generation").collect();
+        assert_eq!(generations.len(), GENERATIONS as usize);
+        let mut best = initial;
+        for (i, &&(_, generation, fitness)) in generations.iter().enumerate() {
+            assert_eq!(generation, i as u64);
+            let improved = events.contains(&("improvement", generation, fitness));
+            assert_eq!(improved, fitness < best, "generation {generation}");
+            assert!(fitness <= best);
+            best = fitness;
+        }
+        assert!(events.iter().any(|e| e.0 == "improvement"));
+        // a shape is added every 10 generations, up to 4
+        let added: Vec<_> = events.iter().filter(|e| e.0 == "shape_added").collect();
+        assert_eq!(added.iter().map(|e| e.1).collect::<Vec<_>>(), [10, 20, 30]);
+        assert_eq!(events.last().unwrap().0, "finish");
+    }
+
+    #[test]
+    fn csv_rows_are_written_every_interval() {
+        let out = Shared::default();
+        let events = run(CsvLogger::new(out.clone(), 15).unwrap());
+        let lines = out.lines();
+        assert_eq!(lines[0], "generation,seconds,fitness,best_fitness,shapes");
+        assert_eq!(lines.len(), 4);
+        for (line, generation) in lines[1..].iter().zip([0, 15, 30]) {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields.len(), 5, "{line}");
+            assert_eq!(fields[0], generation.to_string());
+            let best = events
+                .iter()
+                .find(|e| e.0 == "generation" && e.1 == generation)
+                .unwrap()
+                .2;
+            let normalised = best / (24.0 * 16.0);
+            assert_eq!(fields[3].parse::<f64>().unwrap(), normalised);
+            assert_eq!(fields[4], (1 + generation / 10).to_string());
+        }
+    }
+
+    #[test]
+    fn json_lines_report_every_event() {
+        let out = Shared::default();
+        let events = run(JsonLinesLogger::new(out.clone(), 15));
+        let lines: Vec<Json> = out
+            .lines()
+            .iter()
+            .map(|line| Json::parse(line).unwrap())
+            .collect();
+        let logged = |event: &str| -> Vec<u64> {
+            lines
+                .iter()
+                .filter(|j| j.get("event").and_then(Json::as_str) == Some(event))
+                .map(|j| j.get("generation").and_then(Json::as_u64).unwrap())
+                .collect()
+        };
+        let recorded = |event: &str| -> Vec<u64> {
+            events
+                .iter()
+                .filter(|e| e.0 == event)
+                .map(|e| e.1)
+                .collect()
+        };
+        assert_eq!(logged("generation"), [0, 15, 30]);
+        assert_eq!(logged("improvement"), recorded("improvement"));
+        assert_eq!(logged("shape_added"), [10, 20, 30]);
+        assert_eq!(logged("finish"), [GENERATIONS - 1]);
+        for json in &lines {
+            for key in ["seconds", "fitness", "best_fitness", "shapes"] {
+                assert!(json.get(key).and_then(Json::as_f64).is_some(), "{key}");
+            }
+        }
+    }
+
+    #[test]
+    fn frames_are_saved_every_interval() {
+        let dir = std::env::temp_dir().join(format!("shapeme-frames-{}", std::process::id()));
+        run(FrameSaver::new(&dir, 15, FrameBuffer::new(24, 16)).unwrap());
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            names,
+            ["frame_000000.png", "frame_000001.png", "frame_000002.png"]
+        );
+    }
+}
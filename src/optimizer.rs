@@ -0,0 +1,71 @@
+//! Optimisers that an application can drive one generation at a time.
+
+use crate::observer::{self, Observer};
+use crate::{Json, Shape, Target};
+use std::io;
+
+/// An optimisation in progress.
+///
+/// Fitness values are total errors as returned by `Target::error` - lower is
+/// better; `Target::normalise` turns them into the per pixel values shown in
+/// the logs.
+pub trait Optimizer<S: Shape> {
+    /// Run one generation. Only fails if one of the observers does.
+    fn step(&mut self) -> io::Result<()>;
+
+    /// Number of generations run so far
+    fn generation(&self) -> u64;
+
+    /// Number of generations each `step()` runs
+    fn generations_per_step(&self) -> u64 {
+        1
+    }
+
+    /// The best solution found so far
+    fn best(&self) -> &[S];
+
+    /// Error of `best()`
+    fn best_fitness(&self) -> f64;
+
+    /// The current solution - for a population, its best member
+    fn current(&self) -> &[S];
+
+    /// Error of `current()`
+    fn fitness(&self) -> f64;
+
+    /// The image being approximated
+    fn target(&self) -> &Target;
+
+    /// One line summary of the progress, for logs
+    fn status(&self) -> String;
+
+    /// Everything needed to continue the run later, see `Checkpoint`
+    fn snapshot(&self) -> Json;
+
+    /// The observers notified of the progress
+    fn observers(&mut self) -> &mut Vec<Box<dyn Observer<S>>>;
+
+    /// Attach an observer
+    fn observe(&mut self, observer: impl Observer<S> + 'static)
+    where
+        Self: Sized,
+    {
+        self.observers().push(Box::new(observer));
+    }
+
+    /// Step until `generations` generations have been run
+    fn run(&mut self, generations: u64) -> io::Result<()> {
+        while self.generation() < generations {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Tell the observers that the run is over
+    fn finish(&mut self) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        observer::notify(self, |o, opt| o.on_finish(opt))
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    Circle, Colour, Ellipse, FrameBuffer, Rect, Rectangle, RotatedRectangle, Shape, ShapeKind,
+    Triangle,
+};
+use rand_core::RngCore;
+use std::fmt;
+use std::io::{self, Write};
+
+/// The region a shape roughly covers, used to convert between primitive kinds.
+pub(crate) struct Footprint {
+    pub(crate) centre: (f32, f32),
+    pub(crate) half_size: (f32, f32),
+}
+
+impl Footprint {
+    pub(crate) fn from_bounds(x1: f32, y1: f32, x2: f32, y2: f32) -> Self {
+        Self {
+            centre: ((x1 + x2) / 2.0, (y1 + y2) / 2.0),
+            half_size: ((x2 - x1).abs() / 2.0, (y2 - y1).abs() / 2.0),
+        }
+    }
+
+    pub(crate) fn bounds(&self) -> (f32, f32, f32, f32) {
+        let (cx, cy) = self.centre;
+        let (hw, hh) = self.half_size;
+        (cx - hw, cy - hh, cx + hw, cy + hh)
+    }
+}
+
+// round and clamp a footprint coordinate to the u16 range used by the shapes
+pub(crate) fn to_u16(v: f32) -> u16 {
+    v.round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+// whether saved geometry fits the u16 range used by the shapes, for
+// `from_geometry`, which rejects it rather than clamp it like `to_u16`
+pub(crate) fn in_range(geometry: &[f32]) -> bool {
+    geometry.iter().all(|v| (0.0..=u16::MAX as f32).contains(v))
+}
+
+/// Any of the concrete shapes, so that a single genome can mix primitive kinds.
+#[derive(Clone)]
+pub enum Primitive {
+    Triangle(Triangle),
+    Circle(Circle),
+    Ellipse(Ellipse),
+    Rectangle(Rectangle),
+    RotatedRectangle(RotatedRectangle),
+}
+
+// forward a call to whichever shape the primitive holds
+macro_rules! dispatch {
+    ($self:expr, $s:ident => $body:expr) => {
+        match $self {
+            Primitive::Triangle($s) => $body,
+            Primitive::Circle($s) => $body,
+            Primitive::Ellipse($s) => $body,
+            Primitive::Rectangle($s) => $body,
+            Primitive::RotatedRectangle($s) => $body,
+        }
+    };
+}
+
+const KINDS: [ShapeKind; 5] = [
+    ShapeKind::Triangle,
+    ShapeKind::Circle,
+    ShapeKind::Ellipse,
+    ShapeKind::Rectangle,
+    ShapeKind::RotatedRectangle,
+];
+
+impl fmt::Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        dispatch!(self, s => s.fmt(f))
+    }
+}
+
+impl Primitive {
+    /// Random shape of the given kind. `ShapeKind::Mixed` picks a kind at random.
+    pub fn random_of_kind<R: RngCore + ?Sized>(
+        rng: &mut R,
+        kind: ShapeKind,
+        width: u16,
+        height: u16,
+    ) -> Self {
+        match kind {
+            ShapeKind::Triangle => Primitive::Triangle(Triangle::random(rng, width, height)),
+            ShapeKind::Circle => Primitive::Circle(Circle::random(rng, width, height)),
+            ShapeKind::Ellipse => Primitive::Ellipse(Ellipse::random(rng, width, height)),
+            ShapeKind::Rectangle => Primitive::Rectangle(Rectangle::random(rng, width, height)),
+            ShapeKind::RotatedRectangle => {
+                Primitive::RotatedRectangle(RotatedRectangle::random(rng, width, height))
+            }
+            ShapeKind::Mixed => {
+                let kind = KINDS[(rng.next_u64() % KINDS.len() as u64) as usize];
+                Self::random_of_kind(rng, kind, width, height)
+            }
+        }
+    }
+
+    /// Convert to another kind of primitive, keeping colour and roughly the same footprint.
+    pub fn convert(&self, kind: ShapeKind, width: u16, height: u16) -> Self {
+        let fp = dispatch!(self, s => s.footprint());
+        let colour = self.colour().clone();
+        let mut p = match kind {
+            ShapeKind::Triangle => Primitive::Triangle(Triangle::from_footprint(&fp, colour)),
+            ShapeKind::Circle => Primitive::Circle(Circle::from_footprint(&fp, colour)),
+            ShapeKind::Ellipse => Primitive::Ellipse(Ellipse::from_footprint(&fp, colour)),
+            ShapeKind::Rectangle => Primitive::Rectangle(Rectangle::from_footprint(&fp, colour)),
+            ShapeKind::RotatedRectangle => {
+                Primitive::RotatedRectangle(RotatedRectangle::from_footprint(&fp, colour))
+            }
+            ShapeKind::Mixed => self.clone(),
+        };
+        p.normalise(width, height);
+        p
+    }
+
+    // mutate: turn into a different, randomly chosen, kind of primitive
+    fn mutate_kind<R: RngCore + ?Sized>(&mut self, rng: &mut R, width: u16, height: u16) {
+        let others: Vec<ShapeKind> = KINDS.into_iter().filter(|&k| k != self.kind()).collect();
+        let kind = others[(rng.next_u64() % others.len() as u64) as usize];
+        *self = self.convert(kind, width, height);
+    }
+}
+
+impl Shape for Primitive {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+        Self::random_of_kind(rng, ShapeKind::Mixed, width, height)
+    }
+
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    ) {
+        dispatch!(self, s => s.mutate_geometry(rng, width, height, delta))
+    }
+
+    fn normalise(&mut self, width: u16, height: u16) {
+        dispatch!(self, s => s.normalise(width, height))
+    }
+
+    fn snap_to_pixels(&mut self) {
+        dispatch!(self, s => s.snap_to_pixels())
+    }
+
+    fn colour(&self) -> &Colour {
+        dispatch!(self, s => s.colour())
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        dispatch!(self, s => s.colour_mut())
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        dispatch!(self, s => s.draw(fb))
+    }
+
+    fn bounds(&self) -> Rect {
+        dispatch!(self, s => s.bounds())
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        dispatch!(self, s => s.write_svg(w))
+    }
+
+    fn kind(&self) -> ShapeKind {
+        dispatch!(self, s => s.kind())
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        dispatch!(self, s => s.geometry())
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        Some(match kind {
+            ShapeKind::Triangle => {
+                Primitive::Triangle(Triangle::from_geometry(kind, geometry, colour)?)
+            }
+            ShapeKind::Circle => Primitive::Circle(Circle::from_geometry(kind, geometry, colour)?),
+            ShapeKind::Ellipse => {
+                Primitive::Ellipse(Ellipse::from_geometry(kind, geometry, colour)?)
+            }
+            ShapeKind::Rectangle => {
+                Primitive::Rectangle(Rectangle::from_geometry(kind, geometry, colour)?)
+            }
+            ShapeKind::RotatedRectangle => Primitive::RotatedRectangle(
+                RotatedRectangle::from_geometry(kind, geometry, colour)?,
+            ),
+            ShapeKind::Mixed => return None,
+        })
+    }
+
+    // Apply a random mutation - one in ten changes the kind of primitive,
+    // the rest are the usual mutations of the shape held
+    fn mutate<R: RngCore + ?Sized>(&mut self, rng: &mut R, width: u16, height: u16) {
+        if rng.next_u64().is_multiple_of(10) {
+            self.mutate_kind(rng, width, height);
+        } else {
+            dispatch!(self, s => s.mutate(rng, width, height))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_round_trips_for_every_kind() {
+        let geometries: [(ShapeKind, &[f32]); 5] = [
+            (ShapeKind::Triangle, &[1.0, 2.0, 8.0, 3.0, 4.0, 9.0]),
+            (ShapeKind::Circle, &[5.0, 6.0, 3.0]),
+            (ShapeKind::Ellipse, &[5.0, 6.0, 3.0, 2.0]),
+            (ShapeKind::Rectangle, &[1.0, 2.0, 7.0, 8.0]),
+            (ShapeKind::RotatedRectangle, &[5.0, 6.0, 3.0, 2.0, 30.0]),
+        ];
+        for (kind, geometry) in geometries {
+            let colour = Colour::new(40, 50, 60, 70);
+            let p = Primitive::from_geometry(kind, geometry, colour).unwrap();
+            assert_eq!(p.kind(), kind);
+            assert_eq!(p.geometry(), geometry.to_vec(), "{kind}");
+            let copy =
+                Primitive::from_geometry(p.kind(), &p.geometry(), p.colour().clone()).unwrap();
+            assert_eq!(copy.geometry(), geometry.to_vec(), "{kind}");
+            assert_eq!(copy.colour().rgba(), [40, 50, 60, 70]);
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_and_bad_geometry() {
+        let colour = Colour::new(0, 0, 0, 50);
+        assert!(
+            Primitive::from_geometry(ShapeKind::Mixed, &[1.0, 2.0, 3.0], colour.clone()).is_none()
+        );
+        assert!(Primitive::from_geometry(ShapeKind::Circle, &[1.0, 2.0], colour.clone()).is_none());
+        assert!(Primitive::from_geometry(ShapeKind::Circle, &[1.0, 2.0, -3.0], colour).is_none());
+    }
+
+    #[test]
+    fn random_shapes_round_trip() {
+        let mut rng = mersenne_twister_rs::MersenneTwister64::new(7);
+        for _ in 0..200 {
+            let p = Primitive::random(&mut rng, 64, 48);
+            let copy =
+                Primitive::from_geometry(p.kind(), &p.geometry(), p.colour().clone()).unwrap();
+            assert_eq!(copy.geometry(), p.geometry());
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use crate::primitive::{Footprint, in_range, to_u16};
+use crate::{Colour, FrameBuffer, Rect, Shape, ShapeKind, jitter, rand_between, rand_u16_x4};
+use rand_core::RngCore;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Axis-aligned rectangle, both corners inclusive
+#[derive(Clone)]
+pub struct Rectangle {
+    corners: [(u16, u16); 2],
+    colour: Colour,
+}
+
+impl fmt::Display for Rectangle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        write!(f, "Rectangle [({x1},{y1}),({x2},{y2})] {}", self.colour)
+    }
+}
+
+impl Rectangle {
+    pub(crate) fn footprint(&self) -> Footprint {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        Footprint::from_bounds(x1 as f32, y1 as f32, x2 as f32, y2 as f32)
+    }
+
+    pub(crate) fn from_footprint(fp: &Footprint, colour: Colour) -> Self {
+        let (x1, y1, x2, y2) = fp.bounds();
+        Rectangle {
+            corners: [(to_u16(x1), to_u16(y1)), (to_u16(x2), to_u16(y2))],
+            colour,
+        }
+    }
+}
+
+impl Shape for Rectangle {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+        let (a, b, c, d) = rand_u16_x4(rng);
+        let mut r = Rectangle {
+            colour: Colour::random(rng),
+            corners: [(a % width, b % height), (c % width, d % height)],
+        };
+        r.normalise(width, height);
+        r
+    }
+
+    // mutate: randomly move corners
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    ) {
+        for corner in &mut self.corners {
+            corner.0 = jitter(rng, corner.0, delta, 0, width as i64 - 1);
+            corner.1 = jitter(rng, corner.1, delta, 0, height as i64 - 1);
+        }
+        self.normalise(width, height);
+    }
+
+    // order the corners top-left, bottom-right and clamp them to the canvas
+    fn normalise(&mut self, width: u16, height: u16) {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        let max_x = width.saturating_sub(1);
+        let max_y = height.saturating_sub(1);
+        self.corners = [
+            (x1.min(x2).min(max_x), y1.min(y2).min(max_y)),
+            (x1.max(x2).min(max_x), y1.max(y2).min(max_y)),
+        ];
+    }
+
+    fn colour(&self) -> &Colour {
+        &self.colour
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        &mut self.colour
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        fb.fill_rect(x1, y1, x2, y2, &self.colour);
+    }
+
+    fn bounds(&self) -> Rect {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        Rect::around(x1 as f32, y1 as f32, x2 as f32, y2 as f32)
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        writeln!(
+            w,
+            r#"<rect x="{x1}" y="{y1}" width="{}" height="{}" style="{}"/>"#,
+            x2.abs_diff(x1) as u32 + 1,
+            y2.abs_diff(y1) as u32 + 1,
+            self.colour.svg_style()
+        )
+    }
+
+    fn kind(&self) -> ShapeKind {
+        ShapeKind::Rectangle
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        let [(x1, y1), (x2, y2)] = self.corners;
+        [x1, y1, x2, y2].map(f32::from).to_vec()
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        match (kind, geometry) {
+            (ShapeKind::Rectangle, &[x1, y1, x2, y2]) if in_range(geometry) => {
+                let [x1, y1, x2, y2] = [x1, y1, x2, y2].map(to_u16);
+                // top-left, bottom-right as everywhere else
+                Some(Rectangle {
+                    corners: [(x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2))],
+                    colour,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Rectangle rotated about its centre by `angle` degrees (clockwise, as in SVG)
+#[derive(Clone)]
+pub struct RotatedRectangle {
+    centre: (u16, u16),
+    half_size: (u16, u16),
+    angle: u16,
+    colour: Colour,
+}
+
+impl fmt::Display for RotatedRectangle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.centre;
+        let (hw, hh) = self.half_size;
+        write!(
+            f,
+            "RotatedRectangle ({x},{y}) half=({hw},{hh}) angle={} {}",
+            self.angle, self.colour
+        )
+    }
+}
+
+impl RotatedRectangle {
+    // the unrotated extent; close enough when switching primitive kind
+    pub(crate) fn footprint(&self) -> Footprint {
+        Footprint {
+            centre: (self.centre.0 as f32, self.centre.1 as f32),
+            half_size: (self.half_size.0 as f32, self.half_size.1 as f32),
+        }
+    }
+
+    pub(crate) fn from_footprint(fp: &Footprint, colour: Colour) -> Self {
+        RotatedRectangle {
+            centre: (to_u16(fp.centre.0), to_u16(fp.centre.1)),
+            half_size: (to_u16(fp.half_size.0), to_u16(fp.half_size.1)),
+            angle: 0,
+            colour,
+        }
+    }
+
+    fn corners(&self) -> [(f32, f32); 4] {
+        let (cx, cy) = (self.centre.0 as f32, self.centre.1 as f32);
+        let (hw, hh) = (self.half_size.0 as f32, self.half_size.1 as f32);
+        let (sin, cos) = (self.angle as f32).to_radians().sin_cos();
+        [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)]
+            .map(|(dx, dy)| (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos))
+    }
+}
+
+impl Shape for RotatedRectangle {
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self {
+        let x = rand_between(rng, 0, width as i64 - 1) as u16;
+        let y = rand_between(rng, 0, height as i64 - 1) as u16;
+        let hw = rand_between(rng, 1, (width / 4).max(1) as i64) as u16;
+        let hh = rand_between(rng, 1, (height / 4).max(1) as i64) as u16;
+        let angle = rand_between(rng, 0, 179) as u16;
+        let mut r = RotatedRectangle {
+            centre: (x, y),
+            half_size: (hw, hh),
+            angle,
+            colour: Colour::random(rng),
+        };
+        r.normalise(width, height);
+        r
+    }
+
+    // mutate: move the centre, resize and rotate (by up to `delta` degrees)
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    ) {
+        self.centre.0 = jitter(rng, self.centre.0, delta, 0, width as i64 - 1);
+        self.centre.1 = jitter(rng, self.centre.1, delta, 0, height as i64 - 1);
+        self.half_size.0 = jitter(rng, self.half_size.0, delta, 1, width as i64);
+        self.half_size.1 = jitter(rng, self.half_size.1, delta, 1, height as i64);
+        let turn = rand_between(rng, -(delta as i64), delta as i64);
+        self.angle = (self.angle as i64 + turn).rem_euclid(180) as u16;
+        self.normalise(width, height);
+    }
+
+    // keep the centre on the canvas, the size non-zero and the angle in [0, 180)
+    fn normalise(&mut self, width: u16, height: u16) {
+        self.centre.0 = self.centre.0.min(width.saturating_sub(1));
+        self.centre.1 = self.centre.1.min(height.saturating_sub(1));
+        self.half_size.0 = self.half_size.0.clamp(1, width.max(1));
+        self.half_size.1 = self.half_size.1.clamp(1, height.max(1));
+        self.angle %= 180;
+    }
+
+    fn colour(&self) -> &Colour {
+        &self.colour
+    }
+
+    fn colour_mut(&mut self) -> &mut Colour {
+        &mut self.colour
+    }
+
+    fn draw(&self, fb: &mut FrameBuffer) {
+        fb.fill_convex_polygon(&self.corners(), &self.colour);
+    }
+
+    fn bounds(&self) -> Rect {
+        let corners = self.corners();
+        let xs = corners.map(|c| c.0);
+        let ys = corners.map(|c| c.1);
+        Rect::around(
+            xs.into_iter().fold(f32::INFINITY, f32::min),
+            ys.into_iter().fold(f32::INFINITY, f32::min),
+            xs.into_iter().fold(f32::NEG_INFINITY, f32::max),
+            ys.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        )
+    }
+
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (x, y) = self.centre;
+        let (hw, hh) = self.half_size;
+        writeln!(
+            w,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" transform="rotate({} {x} {y})" style="{}"/>"#,
+            x as i32 - hw as i32,
+            y as i32 - hh as i32,
+            2 * hw as u32,
+            2 * hh as u32,
+            self.angle,
+            self.colour.svg_style()
+        )
+    }
+
+    fn kind(&self) -> ShapeKind {
+        ShapeKind::RotatedRectangle
+    }
+
+    fn geometry(&self) -> Vec<f32> {
+        let (x, y) = self.centre;
+        let (hw, hh) = self.half_size;
+        [x, y, hw, hh, self.angle].map(f32::from).to_vec()
+    }
+
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self> {
+        match (kind, geometry) {
+            // any angle is fine, the rectangle looks the same every 180 degrees
+            (ShapeKind::RotatedRectangle, &[x, y, hw, hh, angle])
+                if in_range(&[x, y, hw, hh]) && hw >= 1.0 && hh >= 1.0 && angle.is_finite() =>
+            {
+                Some(RotatedRectangle {
+                    centre: (to_u16(x), to_u16(y)),
+                    half_size: (to_u16(hw), to_u16(hh)),
+                    angle: to_u16(angle.rem_euclid(180.0)) % 180,
+                    colour,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg<S: Shape>(shape: &S) -> String {
+        let mut out = Vec::new();
+        shape.write_svg(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn rectangle_geometry_round_trips() {
+        let colour = Colour::new(10, 20, 30, 40);
+        let r =
+            Rectangle::from_geometry(ShapeKind::Rectangle, &[3.0, 4.0, 9.0, 12.0], colour).unwrap();
+        assert_eq!(r.geometry(), vec![3.0, 4.0, 9.0, 12.0]);
+        let copy = Rectangle::from_geometry(r.kind(), &r.geometry(), r.colour().clone()).unwrap();
+        assert_eq!(copy.geometry(), r.geometry());
+        assert_eq!(copy.colour().rgba(), [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn rectangle_corners_are_ordered() {
+        let colour = Colour::new(0, 0, 0, 50);
+        let r = Rectangle::from_geometry(ShapeKind::Rectangle, &[200.0, 200.0, 10.0, 10.0], colour)
+            .unwrap();
+        assert_eq!(r.geometry(), vec![10.0, 10.0, 200.0, 200.0]);
+        assert!(svg(&r).starts_with(r#"<rect x="10" y="10" width="191" height="191""#));
+    }
+
+    #[test]
+    fn rectangle_rejects_other_kinds_and_lengths() {
+        let colour = Colour::new(0, 0, 0, 50);
+        assert!(
+            Rectangle::from_geometry(ShapeKind::Rectangle, &[1.0, 2.0, 3.0], colour.clone())
+                .is_none()
+        );
+        assert!(
+            Rectangle::from_geometry(ShapeKind::Ellipse, &[1.0, 2.0, 3.0, 4.0], colour).is_none()
+        );
+    }
+
+    #[test]
+    fn rectangle_rejects_out_of_range() {
+        let colour = Colour::new(0, 0, 0, 50);
+        for geometry in [[-1.0, 2.0, 3.0, 4.0], [1.0, 2.0, 3.0, f32::NAN]] {
+            assert!(
+                Rectangle::from_geometry(ShapeKind::Rectangle, &geometry, colour.clone()).is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn rectangle_svg() {
+        let r = Rectangle::from_geometry(
+            ShapeKind::Rectangle,
+            &[2.0, 3.0, 2.0, 7.0],
+            Colour::new(255, 0, 16, 50),
+        )
+        .unwrap();
+        assert_eq!(
+            svg(&r),
+            "<rect x=\"2\" y=\"3\" width=\"1\" height=\"5\" \
+             style=\"fill:#ff0010;stroke:#000000;stroke-width:0;fill-opacity:0.50;\"/>\n"
+        );
+    }
+
+    #[test]
+    fn rotated_rectangle_geometry_round_trips() {
+        let colour = Colour::new(1, 2, 3, 4);
+        let geometry = [20.0, 30.0, 5.0, 8.0, 45.0];
+        let r = RotatedRectangle::from_geometry(ShapeKind::RotatedRectangle, &geometry, colour)
+            .unwrap();
+        assert_eq!(r.geometry(), geometry.to_vec());
+        let copy =
+            RotatedRectangle::from_geometry(r.kind(), &r.geometry(), r.colour().clone()).unwrap();
+        assert_eq!(copy.geometry(), geometry.to_vec());
+    }
+
+    #[test]
+    fn rotated_rectangle_angle_is_wrapped() {
+        let colour = Colour::new(1, 2, 3, 4);
+        for (angle, wrapped) in [(180.0, 0.0), (200.0, 20.0), (-30.0, 150.0), (179.7, 0.0)] {
+            let r = RotatedRectangle::from_geometry(
+                ShapeKind::RotatedRectangle,
+                &[20.0, 30.0, 5.0, 8.0, angle],
+                colour.clone(),
+            )
+            .unwrap();
+            assert_eq!(r.geometry()[4], wrapped, "angle {angle}");
+        }
+    }
+
+    #[test]
+    fn rotated_rectangle_rejects_bad_geometry() {
+        let colour = Colour::new(1, 2, 3, 4);
+        for geometry in [
+            &[20.0, 30.0, 5.0, 8.0][..],
+            &[20.0, 30.0, 0.0, 8.0, 10.0],
+            &[-20.0, 30.0, 5.0, 8.0, 10.0],
+            &[20.0, 30.0, 5.0, 8.0, f32::NAN],
+        ] {
+            assert!(
+                RotatedRectangle::from_geometry(
+                    ShapeKind::RotatedRectangle,
+                    geometry,
+                    colour.clone()
+                )
+                .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn rotated_rectangle_svg() {
+        let r = RotatedRectangle::from_geometry(
+            ShapeKind::RotatedRectangle,
+            &[20.0, 30.0, 5.0, 8.0, 45.0],
+            Colour::new(0, 128, 255, 100),
+        )
+        .unwrap();
+        assert_eq!(
+            svg(&r),
+            "<rect x=\"15\" y=\"22\" width=\"10\" height=\"16\" transform=\"rotate(45 20 30)\" \
+             style=\"fill:#0080ff;stroke:#000000;stroke-width:0;fill-opacity:1.00;\"/>\n"
+        );
+    }
+}
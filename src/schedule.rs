@@ -0,0 +1,192 @@
+//! Cooling schedules for simulated annealing: how the temperature falls
+//! from one generation to the next.
+//!
+//! Apart from `Exponential`, which only needs its rate, the schedules are
+//! fitted to the run: they fall from the initial temperature to
+//! `final_temp` over `schedule_length` generations (see `AnnealingConfig`).
+//! All of them work step by step from the current temperature, so a reheat
+//! when a shape is added carries on from the raised temperature.
+
+use crate::AnnealingConfig;
+
+/// The cooling schedules available on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoolingSchedule {
+    /// `T *= cooling_rate` every generation
+    Exponential,
+    /// `T` falls by the same amount every generation
+    Linear,
+    /// `T = T0 / (1 + c ln(1 + k))` after `k` generations
+    Logarithmic,
+    /// `T = T / (1 + b T)` every generation, after Lundy and Mees
+    LundyMees,
+    /// Lam's schedule: the temperature is raised or lowered to keep the
+    /// rate at which moves are accepted on a target curve, starting high,
+    /// holding at 44% for most of the run and dropping towards the end
+    Adaptive,
+}
+
+named_enum!(CoolingSchedule, "schedule", {
+    Exponential => "exponential",
+    Linear => "linear",
+    Logarithmic => "logarithmic",
+    LundyMees => "lundy-mees",
+    Adaptive => "adaptive",
+});
+
+// Lam's schedule changes the temperature by this factor every generation
+const ADAPTIVE_FACTOR: f64 = 0.999;
+// generations over which the acceptance rate is averaged
+const ADAPTIVE_WINDOW: f64 = 500.0;
+
+/// A schedule set up for one run, see the module documentation
+#[derive(Clone, Debug)]
+pub(crate) struct Cooling {
+    schedule: CoolingSchedule,
+    cooling_rate: f64,
+    pub(crate) initial: f64,
+    final_temp: f64,
+    length: u64,
+    /// Moving average of the moves accepted, for `Adaptive`
+    pub(crate) acceptance: f64,
+}
+
+impl Cooling {
+    /// The schedule of `config` for a run starting at temperature `initial`
+    pub(crate) fn new(config: &AnnealingConfig, initial: f64) -> Self {
+        Self {
+            schedule: config.schedule,
+            cooling_rate: config.cooling_rate,
+            initial,
+            final_temp: config.final_temp.min(initial),
+            length: config.schedule_length.max(1),
+            acceptance: 0.5,
+        }
+    }
+
+    /// The temperature to go on from `t` with, once `generation`
+    /// generations have been run
+    pub(crate) fn next(&self, t: f64, generation: u64) -> f64 {
+        let (t0, tf, n) = (self.initial, self.final_temp, self.length as f64);
+        match self.schedule {
+            CoolingSchedule::Exponential => t * self.cooling_rate,
+            CoolingSchedule::Linear => (t - (t0 - tf) / n).max(tf),
+            CoolingSchedule::Logarithmic => {
+                // reaching tf after n generations
+                let c = (t0 / tf - 1.0) / (1.0 + n).ln();
+                let k = generation as f64;
+                t * (1.0 + c * k.ln_1p()) / (1.0 + c * (k + 1.0).ln_1p())
+            }
+            CoolingSchedule::LundyMees => {
+                // 1 / T grows by b every generation, from 1 / t0 to 1 / tf
+                let b = (1.0 / tf - 1.0 / t0) / n;
+                t / (1.0 + b * t)
+            }
+            CoolingSchedule::Adaptive => {
+                if self.acceptance > lam_rate(generation as f64 / n) {
+                    t * ADAPTIVE_FACTOR
+                } else {
+                    t / ADAPTIVE_FACTOR
+                }
+            }
+        }
+    }
+
+    /// Record whether the move of a generation was accepted
+    pub(crate) fn record(&mut self, accepted: bool) {
+        let accepted = if accepted { 1.0 } else { 0.0 };
+        self.acceptance += (accepted - self.acceptance) / ADAPTIVE_WINDOW;
+    }
+}
+
+// The target acceptance rate of Lam's schedule, `done` of the way through
+// the run (Swartz's approximation of Lam and Delosme's curve)
+fn lam_rate(done: f64) -> f64 {
+    if done < 0.15 {
+        0.44 + 0.56 * 560f64.powf(-done / 0.15)
+    } else if done < 0.65 {
+        0.44
+    } else {
+        0.44 * 440f64.powf(-(done - 0.65) / 0.35)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: f64 = 2.0;
+    const T_END: f64 = 1e-3;
+    const LENGTH: u64 = 5000;
+
+    fn cooling(schedule: CoolingSchedule) -> Cooling {
+        let config = AnnealingConfig::default()
+            .schedule(schedule)
+            .cooling_rate((T_END / T0).powf(1.0 / LENGTH as f64))
+            .final_temp(T_END)
+            .schedule_length(LENGTH);
+        Cooling::new(&config, T0)
+    }
+
+    // the temperatures over the schedule, from the initial one on
+    fn run(cooling: &mut Cooling, accepted: bool) -> Vec<f64> {
+        let mut temperatures = vec![cooling.initial];
+        for generation in 0..LENGTH {
+            let t = cooling.next(temperatures[temperatures.len() - 1], generation);
+            temperatures.push(t);
+            cooling.record(accepted);
+        }
+        temperatures
+    }
+
+    #[test]
+    fn schedules_fall_from_t0_to_t_end() {
+        for schedule in [
+            CoolingSchedule::Exponential,
+            CoolingSchedule::Linear,
+            CoolingSchedule::Logarithmic,
+            CoolingSchedule::LundyMees,
+        ] {
+            let temperatures = run(&mut cooling(schedule), false);
+            assert_eq!(temperatures[0], T0, "{schedule}");
+            let end = temperatures[LENGTH as usize];
+            assert!(
+                (end - T_END).abs() < 1e-6 * T_END.max(end),
+                "{schedule}: {end}"
+            );
+            assert!(
+                temperatures.windows(2).all(|w| w[1] <= w[0]),
+                "{schedule} is not monotone"
+            );
+        }
+    }
+
+    #[test]
+    fn linear_schedule_stays_at_t_end() {
+        let cooling = cooling(CoolingSchedule::Linear);
+        assert_eq!(cooling.next(T_END, LENGTH), T_END);
+    }
+
+    #[test]
+    fn final_temperature_is_never_above_initial() {
+        let config = AnnealingConfig::default()
+            .schedule(CoolingSchedule::Linear)
+            .final_temp(5.0);
+        let cooling = Cooling::new(&config, 1.0);
+        assert!(cooling.next(1.0, 0) <= 1.0);
+    }
+
+    #[test]
+    fn adaptive_schedule_follows_the_acceptance_rate() {
+        // moves always accepted: once the average acceptance has caught up
+        // with the early target of close to 1, it cools steadily
+        let temperatures = run(&mut cooling(CoolingSchedule::Adaptive), true);
+        assert_eq!(temperatures[0], T0);
+        let half = LENGTH as usize / 2;
+        assert!(temperatures[half..].windows(2).all(|w| w[1] < w[0]));
+        assert!(temperatures[LENGTH as usize] < T0);
+        // never accepted: it heats up
+        let temperatures = run(&mut cooling(CoolingSchedule::Adaptive), false);
+        assert!(temperatures[1..].windows(2).all(|w| w[1] > w[0]));
+    }
+}
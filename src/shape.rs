@@ -0,0 +1,83 @@
+use crate::{Colour, FrameBuffer, Rect};
+use rand_core::RngCore;
+use std::fmt;
+use std::io::{self, Write};
+
+/// A semi-transparent primitive that can be evolved to approximate an image.
+pub trait Shape: Clone + fmt::Display + Send + Sync {
+    /// Random colour and random placement inside a `width` x `height` canvas
+    fn random<R: RngCore + ?Sized>(rng: &mut R, width: u16, height: u16) -> Self;
+
+    /// Randomly perturb the geometry by up to `delta` pixels
+    fn mutate_geometry<R: RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        width: u16,
+        height: u16,
+        delta: u16,
+    );
+
+    /// Turn an invalid shape (e.g. outside the canvas) into a valid one
+    fn normalise(&mut self, width: u16, height: u16);
+
+    fn colour(&self) -> &Colour;
+
+    fn colour_mut(&mut self) -> &mut Colour;
+
+    /// Rasterise onto `fb`, alpha blending with the existing contents
+    fn draw(&self, fb: &mut FrameBuffer);
+
+    /// A rectangle containing every pixel `draw` may touch
+    fn bounds(&self) -> Rect;
+
+    /// Write the shape as a single SVG element
+    fn write_svg<W: Write>(&self, w: &mut W) -> io::Result<()>;
+
+    /// The kind of primitive held
+    fn kind(&self) -> ShapeKind;
+
+    /// Geometry as a flat list of numbers, in the order `from_geometry` takes them
+    fn geometry(&self) -> Vec<f32>;
+
+    /// Rebuild a shape saved with `kind` and `geometry`; `None` if this type
+    /// cannot hold that kind or the geometry has the wrong length or is out
+    /// of range
+    fn from_geometry(kind: ShapeKind, geometry: &[f32], colour: Colour) -> Option<Self>;
+
+    /// Round the geometry to whole pixels, for drawing without anti-aliasing.
+    /// Only shapes with sub-pixel positions need to implement it.
+    fn snap_to_pixels(&mut self) {}
+
+    /// Apply a random mutation
+    fn mutate<R: RngCore + ?Sized>(&mut self, rng: &mut R, width: u16, height: u16) {
+        match rng.next_u64() % 10 {
+            0 => *self = Self::random(rng, width, height),
+            1 | 2 => self.mutate_geometry(rng, width, height, 3), // Small moves
+            3 | 4 => self.mutate_geometry(rng, width, height, 10), // Medium moves
+            5 | 6 => self.colour_mut().mutate_colour(rng, 10),
+            7 | 8 => self.colour_mut().mutate_colour(rng, 30),
+            _ => self.colour_mut().mutate_alpha(rng, 10),
+        }
+    }
+}
+
+/// The primitives available on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeKind {
+    Triangle,
+    Circle,
+    Ellipse,
+    Rectangle,
+    RotatedRectangle,
+    /// Any of the above, see [`crate::Primitive`]
+    Mixed,
+}
+
+named_enum!(ShapeKind, "shape", {
+    Triangle => "triangle",
+    Circle => "circle",
+    Ellipse => "ellipse",
+    Rectangle => "rectangle",
+    RotatedRectangle => "rotated-rectangle",
+    Mixed => "mixed",
+});
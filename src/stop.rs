@@ -0,0 +1,334 @@
+//! When to end an optimisation: after a number of generations, a wall
+//! clock budget, on reaching a target fitness or PSNR, or when the best
+//! solution stops improving.
+
+use crate::{FrameBuffer, Optimizer, Shape, interrupt};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Why a run ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// All generations were run
+    Generations(u64),
+    /// The time limit was used up
+    TimeLimit(Duration),
+    /// The best fitness reached the target
+    TargetFitness(f64),
+    /// The PSNR of the best solution reached the target
+    TargetPsnr(f64),
+    /// The best solution did not improve for this many generations
+    Stagnation(u64),
+    /// A signal asked the run to stop, see `interrupt`
+    Interrupted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Generations(n) => write!(f, "generation limit of {n} reached"),
+            StopReason::TimeLimit(d) => write!(f, "time limit of {:.1}s reached", d.as_secs_f64()),
+            StopReason::TargetFitness(v) => write!(f, "target fitness {v} reached"),
+            StopReason::TargetPsnr(v) => write!(f, "target PSNR {v} dB reached"),
+            StopReason::Stagnation(n) => write!(f, "no improvement for {n} generations"),
+            StopReason::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// The conditions ending a run, any one of which is enough.
+///
+/// The time limit and stagnation count start when the criteria are first
+/// checked, so a resumed run gets a fresh budget.
+pub struct StopCriteria {
+    /// Stop once the optimiser has run this many generations
+    pub generations: u64,
+    pub time_limit: Option<Duration>,
+    /// Stop once the best fitness, per pixel as in the logs, is at most this
+    pub target_fitness: Option<f64>,
+    /// Stop once the best solution has at least this PSNR in dB
+    pub target_psnr: Option<f64>,
+    /// Stop after this many generations without improvement
+    pub stagnation: Option<u64>,
+    /// Render with anti-aliasing when measuring the PSNR
+    pub antialias: bool,
+    start: Option<Instant>,
+    best: f64,
+    improved_at: u64,
+    psnr: f64,
+}
+
+impl StopCriteria {
+    pub fn new(generations: u64) -> Self {
+        Self {
+            generations,
+            time_limit: None,
+            target_fitness: None,
+            target_psnr: None,
+            stagnation: None,
+            antialias: false,
+            start: None,
+            best: f64::INFINITY,
+            improved_at: 0,
+            psnr: 0.0,
+        }
+    }
+
+    pub fn time_limit(mut self, time_limit: Option<Duration>) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    pub fn target_fitness(mut self, target_fitness: Option<f64>) -> Self {
+        self.target_fitness = target_fitness;
+        self
+    }
+
+    pub fn target_psnr(mut self, target_psnr: Option<f64>) -> Self {
+        self.target_psnr = target_psnr;
+        self
+    }
+
+    pub fn stagnation(mut self, stagnation: Option<u64>) -> Self {
+        self.stagnation = stagnation;
+        self
+    }
+
+    pub fn antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    /// Why `optimizer` should stop now, if it should. Call before every step.
+    pub fn check<S: Shape>(&mut self, optimizer: &dyn Optimizer<S>) -> Option<StopReason> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let generation = optimizer.generation();
+        if optimizer.best_fitness() < self.best {
+            self.best = optimizer.best_fitness();
+            self.improved_at = generation;
+            // only rendered when the best solution changes
+            if self.target_psnr.is_some() {
+                let target = optimizer.target();
+                let mut fb = FrameBuffer::new(target.reference.width, target.reference.height);
+                fb.antialias = self.antialias;
+                fb.draw_shapes(optimizer.best());
+                self.psnr = target.psnr(&fb);
+            }
+        }
+
+        if interrupt::requested() {
+            return Some(StopReason::Interrupted);
+        }
+        if let Some(v) = self.target_fitness
+            && optimizer.target().normalise(self.best) <= v
+        {
+            return Some(StopReason::TargetFitness(v));
+        }
+        if let Some(v) = self.target_psnr
+            && self.psnr >= v
+        {
+            return Some(StopReason::TargetPsnr(v));
+        }
+        if let Some(n) = self.stagnation
+            && generation - self.improved_at >= n
+        {
+            return Some(StopReason::Stagnation(n));
+        }
+        if let Some(limit) = self.time_limit
+            && start.elapsed() >= limit
+        {
+            return Some(StopReason::TimeLimit(limit));
+        }
+        if generation >= self.generations {
+            return Some(StopReason::Generations(self.generations));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::Observer;
+    use crate::{Json, MetricKind, Target, Triangle};
+    use std::io;
+
+    // An optimiser whose progress is set by the test
+    struct Scripted {
+        target: Target,
+        generation: u64,
+        best: f64,
+        observers: Vec<Box<dyn Observer<Triangle>>>,
+    }
+
+    impl Scripted {
+        // A 10x10 reference of grey 10 with nothing drawn on it: 28.13 dB
+        fn new() -> Self {
+            let mut reference = FrameBuffer::new(10, 10);
+            reference.pixels.fill(10);
+            Self {
+                target: Target::new(reference, MetricKind::Euclidean),
+                generation: 0,
+                best: f64::MAX,
+                observers: Vec::new(),
+            }
+        }
+    }
+
+    impl Optimizer<Triangle> for Scripted {
+        fn step(&mut self) -> io::Result<()> {
+            self.generation += 1;
+            Ok(())
+        }
+
+        fn generation(&self) -> u64 {
+            self.generation
+        }
+
+        fn best(&self) -> &[Triangle] {
+            &[]
+        }
+
+        fn best_fitness(&self) -> f64 {
+            self.best
+        }
+
+        fn current(&self) -> &[Triangle] {
+            &[]
+        }
+
+        fn fitness(&self) -> f64 {
+            self.best
+        }
+
+        fn target(&self) -> &Target {
+            &self.target
+        }
+
+        fn status(&self) -> String {
+            String::new()
+        }
+
+        fn snapshot(&self) -> Json {
+            Json::Null
+        }
+
+        fn observers(&mut self) -> &mut Vec<Box<dyn Observer<Triangle>>> {
+            &mut self.observers
+        }
+    }
+
+    // Check `criteria` once per generation with these best errors, returning
+    // the generation it stopped at and why
+    fn run(mut criteria: StopCriteria, best: &[f64]) -> Option<(u64, StopReason)> {
+        let mut optimizer = Scripted::new();
+        for &fitness in best {
+            optimizer.best = fitness;
+            if let Some(reason) = criteria.check(&optimizer) {
+                return Some((optimizer.generation, reason));
+            }
+            optimizer.step().unwrap();
+        }
+        None
+    }
+
+    #[test]
+    fn generation_limit() {
+        let stop = run(StopCriteria::new(5), &[1.0; 10]);
+        assert_eq!(stop, Some((5, StopReason::Generations(5))));
+        assert_eq!(
+            StopReason::Generations(5).to_string(),
+            "generation limit of 5 reached"
+        );
+        assert_eq!(run(StopCriteria::new(5), &[1.0; 5]), None);
+    }
+
+    #[test]
+    fn target_fitness_is_per_pixel() {
+        // 100 pixels, so a total of 100 is 1 per pixel
+        let criteria = || StopCriteria::new(100).target_fitness(Some(1.0));
+        let stop = run(criteria(), &[1000.0, 500.0, 101.0, 100.0, 50.0]);
+        assert_eq!(stop, Some((3, StopReason::TargetFitness(1.0))));
+        assert_eq!(
+            StopReason::TargetFitness(1.0).to_string(),
+            "target fitness 1 reached"
+        );
+        assert_eq!(run(criteria(), &[1000.0, 500.0, 101.0]), None);
+    }
+
+    #[test]
+    fn target_psnr_of_the_best_solution() {
+        let stop = run(StopCriteria::new(100).target_psnr(Some(28.0)), &[1.0; 3]);
+        assert_eq!(stop, Some((0, StopReason::TargetPsnr(28.0))));
+        assert_eq!(
+            StopReason::TargetPsnr(28.0).to_string(),
+            "target PSNR 28 dB reached"
+        );
+        assert_eq!(
+            run(StopCriteria::new(100).target_psnr(Some(28.5)), &[1.0; 3]),
+            None
+        );
+    }
+
+    #[test]
+    fn stagnation_counts_from_the_last_improvement() {
+        let criteria = || StopCriteria::new(100).stagnation(Some(3));
+        let stop = run(criteria(), &[9.0, 8.0, 8.0, 8.0, 8.0, 8.0]);
+        assert_eq!(stop, Some((4, StopReason::Stagnation(3))));
+        assert_eq!(
+            StopReason::Stagnation(3).to_string(),
+            "no improvement for 3 generations"
+        );
+        // every improvement starts the count again
+        let best = [9.0, 9.0, 9.0, 8.0, 8.0, 8.0, 7.0, 7.0, 7.0];
+        assert_eq!(run(criteria(), &best), None);
+    }
+
+    #[test]
+    fn time_limit_starts_at_the_first_check() {
+        let limit = Duration::from_millis(50);
+        let mut criteria = StopCriteria::new(100).time_limit(Some(limit));
+        let optimizer = Scripted::new();
+        std::thread::sleep(limit);
+        assert_eq!(criteria.check(&optimizer), None);
+        std::thread::sleep(limit);
+        assert_eq!(
+            criteria.check(&optimizer),
+            Some(StopReason::TimeLimit(limit))
+        );
+        assert_eq!(
+            StopReason::TimeLimit(Duration::from_millis(1500)).to_string(),
+            "time limit of 1.5s reached"
+        );
+    }
+
+    #[test]
+    fn any_one_criterion_is_enough() {
+        let criteria = || {
+            StopCriteria::new(6)
+                .time_limit(Some(Duration::from_secs(3600)))
+                .target_fitness(Some(0.5))
+                .target_psnr(Some(40.0))
+                .stagnation(Some(3))
+        };
+        let improving = [900.0, 800.0, 700.0, 600.0, 500.0, 400.0, 300.0];
+        assert_eq!(
+            run(criteria(), &improving),
+            Some((6, StopReason::Generations(6)))
+        );
+        let stuck = [900.0, 800.0, 800.0, 800.0, 800.0];
+        assert_eq!(
+            run(criteria(), &stuck),
+            Some((4, StopReason::Stagnation(3)))
+        );
+        let good = [900.0, 40.0, 40.0];
+        assert_eq!(
+            run(criteria(), &good),
+            Some((1, StopReason::TargetFitness(0.5)))
+        );
+        // when several are met at once, the generation limit comes last
+        let mut limited = criteria();
+        limited.generations = 4;
+        assert_eq!(run(limited, &stuck), Some((4, StopReason::Stagnation(3))));
+    }
+}
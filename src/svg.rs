@@ -0,0 +1,275 @@
+//! Reading back the SVG files written by `save_svg`.
+//!
+//! Only the subset of SVG that `save_svg` produces is understood: one
+//! element per shape with the colour in the `style` attribute, preceded by
+//! an opaque background polygon covering the whole canvas, which also gives
+//! the size of the image. Fully transparent shapes, which `SvgAnimation`
+//! writes for those missing from its last snapshot, are left out, as are
+//! the animations.
+
+use crate::checkpoint::invalid;
+use crate::{Colour, Genome, Primitive, Shape, ShapeKind};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Load the shapes of an SVG file written by `save_svg`
+pub fn load_svg<P: AsRef<Path>, S: Shape>(path: P) -> io::Result<Genome<S>> {
+    parse_svg(&fs::read_to_string(path)?)
+}
+
+pub fn parse_svg<S: Shape>(text: &str) -> io::Result<Genome<S>> {
+    let mut size = None;
+    let mut shapes = Vec::new();
+    for element in text.split('<').skip(1) {
+        let name = element
+            .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .next()
+            .unwrap_or_default();
+        let attr = |key: &str| attribute(element, key);
+        let num = |key: &str| -> io::Result<f32> {
+            attr(key)
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(|| invalid(format!("<{name}> without a numeric {key}")))
+        };
+
+        let (kind, geometry) = match name {
+            "polygon" => {
+                let points = attr("points").ok_or_else(|| invalid("<polygon> without points"))?;
+                let points = parse_points(points)
+                    .ok_or_else(|| invalid(format!("<polygon> with malformed points {points}")))?;
+                match points.len() {
+                    // the background: its far corner is the bottom right pixel
+                    4 if size.is_none() && shapes.is_empty() => {
+                        let max_x = points.iter().map(|p| p.0).fold(0.0, f32::max);
+                        let max_y = points.iter().map(|p| p.1).fold(0.0, f32::max);
+                        size = Some((max_x as u16 + 1, max_y as u16 + 1));
+                        continue;
+                    }
+                    3 => (
+                        ShapeKind::Triangle,
+                        points.iter().flat_map(|&(x, y)| [x, y]).collect(),
+                    ),
+                    n => return Err(invalid(format!("<polygon> with {n} points"))),
+                }
+            }
+            "circle" => (ShapeKind::Circle, vec![num("cx")?, num("cy")?, num("r")?]),
+            "ellipse" => (
+                ShapeKind::Ellipse,
+                vec![num("cx")?, num("cy")?, num("rx")?, num("ry")?],
+            ),
+            "rect" => {
+                let (x, y) = (num("x")?, num("y")?);
+                let (w, h) = (num("width")?, num("height")?);
+                match attr("transform") {
+                    Some(transform) => {
+                        let [angle, cx, cy] = parse_rotate(transform)
+                            .ok_or_else(|| invalid(format!("unsupported transform {transform}")))?;
+                        (
+                            ShapeKind::RotatedRectangle,
+                            vec![cx, cy, w / 2.0, h / 2.0, angle],
+                        )
+                    }
+                    // both corners are inclusive, see Rectangle
+                    None => (ShapeKind::Rectangle, vec![x, y, x + w - 1.0, y + h - 1.0]),
+                }
+            }
+            _ => continue,
+        };
+
+        let (rgb, opacity) = parse_colour(attr("style").unwrap_or_default())
+            .ok_or_else(|| invalid(format!("<{name}> without fill colour and opacity")))?;
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(invalid(format!("<{name}> with fill-opacity {opacity}")));
+        }
+        if opacity == 0.0 {
+            continue;
+        }
+        let [r, g, b] = rgb;
+        let alpha = (opacity * 100.0).round().clamp(0.0, 255.0) as u8;
+        let colour = Colour::new(r, g, b, alpha);
+        let shape = S::from_geometry(kind, &geometry, colour.clone()).ok_or_else(|| {
+            // any kind of shape can be held by a primitive
+            if Primitive::from_geometry(kind, &geometry, colour).is_some() {
+                invalid(format!("cannot use a {kind} as this kind of shape"))
+            } else {
+                invalid(format!("<{name}> with invalid geometry {geometry:?}"))
+            }
+        })?;
+        shapes.push(shape);
+    }
+
+    let (width, height) = size.ok_or_else(|| invalid("no background polygon giving the size"))?;
+    for s in &mut shapes {
+        s.normalise(width, height);
+    }
+    Ok(Genome::new(width, height, shapes))
+}
+
+// value of attribute `key` in the text following a '<'
+fn attribute<'a>(element: &'a str, key: &str) -> Option<&'a str> {
+    let tag = &element[..element.find('>').unwrap_or(element.len())];
+    let mut rest = tag;
+    while let Some(i) = rest.find(key) {
+        let before = rest[..i].chars().next_back();
+        let after = rest[i + key.len()..].trim_start();
+        if before.is_some_and(char::is_whitespace)
+            && let Some(value) = after.strip_prefix('=')
+        {
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            let value = &value[1..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
+        rest = &rest[i + key.len()..];
+    }
+    None
+}
+
+// "x1,y1 x2,y2 ..."
+fn parse_points(points: &str) -> Option<Vec<(f32, f32)>> {
+    let numbers: Vec<f32> = points
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect::<Option<_>>()?;
+    if !numbers.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(numbers.chunks(2).map(|p| (p[0], p[1])).collect())
+}
+
+// "rotate(angle cx cy)"
+fn parse_rotate(transform: &str) -> Option<[f32; 3]> {
+    let args = transform
+        .trim()
+        .strip_prefix("rotate(")?
+        .strip_suffix(')')?;
+    let numbers: Vec<f32> = args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect::<Option<_>>()?;
+    numbers.try_into().ok()
+}
+
+// "fill:#rrggbb;...;fill-opacity:0.50;"
+fn parse_colour(style: &str) -> Option<([u8; 3], f32)> {
+    let mut rgb = None;
+    let mut opacity = None;
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match property.trim() {
+            "fill" => {
+                let hex = value.strip_prefix('#').filter(|h| h.len() == 6)?;
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+                rgb = Some([channel(0)?, channel(2)?, channel(4)?]);
+            }
+            "fill-opacity" => opacity = value.parse::<f32>().ok(),
+            _ => {}
+        }
+    }
+    Some((rgb?, opacity?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Circle, Ellipse, Rectangle, RotatedRectangle, Triangle, write_svg_header};
+
+    fn svg<S: Shape>(shapes: &[S]) -> String {
+        let mut out = Vec::new();
+        write_svg_header(&mut out, 40, 30).unwrap();
+        for s in shapes {
+            s.write_svg(&mut out).unwrap();
+        }
+        out.extend_from_slice(b"</svg>\n");
+        String::from_utf8(out).unwrap()
+    }
+
+    fn shape<S: Shape>(kind: ShapeKind, geometry: &[f32]) -> S {
+        S::from_geometry(kind, geometry, Colour::new(200, 100, 50, 75)).unwrap()
+    }
+
+    fn assert_round_trips<S: Shape>(shapes: Vec<S>) {
+        let genome: Genome<S> = parse_svg(&svg(&shapes)).unwrap();
+        assert_eq!((genome.width, genome.height), (40, 30));
+        assert_eq!(genome.shapes.len(), shapes.len());
+        for (a, b) in shapes.iter().zip(&genome.shapes) {
+            assert_eq!(a.kind(), b.kind());
+            assert_eq!(a.geometry(), b.geometry());
+            assert_eq!(a.colour().rgba(), b.colour().rgba());
+        }
+    }
+
+    const TRIANGLE: &[f32] = &[3.0, 1.0, 20.5, 7.25, 9.0, 28.0];
+    const CIRCLE: &[f32] = &[12.0, 9.0, 5.0];
+    const ELLIPSE: &[f32] = &[12.0, 9.0, 5.0, 3.0];
+    const RECTANGLE: &[f32] = &[2.0, 3.0, 17.0, 21.0];
+    const ROTATED_RECTANGLE: &[f32] = &[20.0, 15.0, 6.0, 4.0, 135.0];
+
+    #[test]
+    fn every_kind_round_trips() {
+        assert_round_trips(vec![shape::<Triangle>(ShapeKind::Triangle, TRIANGLE)]);
+        assert_round_trips(vec![shape::<Circle>(ShapeKind::Circle, CIRCLE)]);
+        assert_round_trips(vec![shape::<Ellipse>(ShapeKind::Ellipse, ELLIPSE)]);
+        assert_round_trips(vec![shape::<Rectangle>(ShapeKind::Rectangle, RECTANGLE)]);
+        assert_round_trips(vec![shape::<RotatedRectangle>(
+            ShapeKind::RotatedRectangle,
+            ROTATED_RECTANGLE,
+        )]);
+        assert_round_trips(vec![
+            shape::<Primitive>(ShapeKind::Triangle, TRIANGLE),
+            shape(ShapeKind::Circle, CIRCLE),
+            shape(ShapeKind::Ellipse, ELLIPSE),
+            shape(ShapeKind::Rectangle, RECTANGLE),
+            shape(ShapeKind::RotatedRectangle, ROTATED_RECTANGLE),
+        ]);
+    }
+
+    #[test]
+    fn transparent_shapes_are_left_out() {
+        let text = svg(&[shape::<Circle>(ShapeKind::Circle, CIRCLE)])
+            .replace("fill-opacity:0.75", "fill-opacity:0.00");
+        let genome: Genome<Circle> = parse_svg(&text).unwrap();
+        assert!(genome.shapes.is_empty());
+    }
+
+    #[test]
+    fn malformed_attributes_are_errors() {
+        let circle = svg(&[shape::<Circle>(ShapeKind::Circle, CIRCLE)]);
+        let triangle = svg(&[shape::<Triangle>(ShapeKind::Triangle, TRIANGLE)]);
+        let rotated = svg(&[shape::<RotatedRectangle>(
+            ShapeKind::RotatedRectangle,
+            ROTATED_RECTANGLE,
+        )]);
+        let cases = [
+            circle.replace(r#"r="5""#, r#"r="five""#),
+            circle.replace(r#"r="5""#, r#"r="-5""#),
+            circle.replace(r#"r="5""#, r#"r="inf""#),
+            circle.replace(r#" cy="9""#, ""),
+            circle.replace("fill:#c86432", "fill:#c864"),
+            circle.replace("fill-opacity:0.75", "fill-opacity:lots"),
+            circle.replace("fill-opacity:0.75", "fill-opacity:1.5"),
+            circle.replace("fill-opacity:0.75", "fill-opacity:NaN"),
+            triangle.replace("20.5,7.25", "20.5;7.25"),
+            triangle.replace("20.5,7.25 ", ""),
+            triangle.replace("points=", "spots="),
+            rotated.replace("rotate(", "skewX("),
+            rotated.replace("rotate(135 20 15)", "rotate(135 20)"),
+        ];
+        for text in cases {
+            assert!(parse_svg::<Primitive>(&text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn shapes_of_another_kind_are_errors() {
+        let text = svg(&[shape::<Circle>(ShapeKind::Circle, CIRCLE)]);
+        let error = parse_svg::<Triangle>(&text).err().unwrap();
+        assert!(error.to_string().contains("cannot use a circle"), "{error}");
+    }
+}
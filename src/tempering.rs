@@ -0,0 +1,519 @@
+//! Parallel tempering (replica exchange): several annealing chains run side
+//! by side, each hotter than the one before, and neighbours periodically
+//! trade their shapes by the Metropolis criterion. Good solutions sink to
+//! the cold chains to be refined, while the hot ones keep exploring, so a
+//! run gets stuck less often than a single chain.
+
+use crate::annealing::Chain;
+use crate::checkpoint::invalid;
+use crate::observer::{self, Observer};
+use crate::{
+    AnnealingConfig, Checkpoint, CountingRng, Json, Optimizer, Shape, Target, checkpoint,
+    stream_seed,
+};
+use mersenne_twister_rs::MersenneTwister64;
+use rand_core::RngCore;
+use std::io;
+
+/// Settings of a `ParallelTempering` run
+#[derive(Clone, Debug)]
+pub struct TemperingConfig {
+    /// Settings of every replica; `temperature` is that of the coldest
+    pub annealing: AnnealingConfig,
+    /// Number of replicas
+    pub replicas: usize,
+    /// Ratio between the temperatures of neighbouring replicas
+    pub ladder: f64,
+    /// Generations between exchanges
+    pub exchange_interval: u64,
+    /// Worker threads (0 = one per core)
+    pub threads: usize,
+}
+
+impl Default for TemperingConfig {
+    fn default() -> Self {
+        Self {
+            annealing: AnnealingConfig::default(),
+            replicas: 8,
+            ladder: 2.0,
+            exchange_interval: 100,
+            threads: 0,
+        }
+    }
+}
+
+impl TemperingConfig {
+    pub fn annealing(mut self, annealing: AnnealingConfig) -> Self {
+        self.annealing = annealing;
+        self
+    }
+
+    pub fn replicas(mut self, replicas: usize) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    pub fn ladder(mut self, ladder: f64) -> Self {
+        self.ladder = ladder;
+        self
+    }
+
+    pub fn exchange_interval(mut self, exchange_interval: u64) -> Self {
+        self.exchange_interval = exchange_interval;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Check that the settings are consistent
+    pub fn validate(&self) -> Result<(), String> {
+        if self.replicas == 0 {
+            return Err("there must be at least one replica".into());
+        }
+        if self.ladder < 1.0 {
+            return Err("the temperature ladder must be at least 1".into());
+        }
+        if self.exchange_interval == 0 {
+            return Err("exchange interval must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+pub struct ParallelTempering<S: Shape> {
+    config: TemperingConfig,
+    target: Target,
+    // coldest first
+    replicas: Vec<Chain<S>>,
+    // decides the exchanges
+    rng: CountingRng<MersenneTwister64>,
+    best_shapes: Vec<S>,
+    best_diff: f64,
+    generation: u64,
+    // exchanges tried and made
+    exchanges: (u64, u64),
+    threads: usize,
+    observers: Vec<Box<dyn Observer<S>>>,
+}
+
+impl<S: Shape> ParallelTempering<S> {
+    /// Start every replica from a single random shape of its own
+    pub fn new(target: Target, config: TemperingConfig) -> io::Result<Self> {
+        Self::from_shapes(target, config, Vec::new())
+    }
+
+    /// Start every replica from `shapes`, e.g. an earlier result, or from a
+    /// single random shape of its own if there are none
+    pub fn from_shapes(
+        target: Target,
+        config: TemperingConfig,
+        shapes: Vec<S>,
+    ) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let annealing = &config.annealing;
+        let mut replicas: Vec<Chain<S>> = (0..config.replicas)
+            .map(|i| {
+                let rng = CountingRng::new(MersenneTwister64::new(replica_seed(annealing, i)));
+                Chain::new(&target, annealing, rng, shapes.clone())
+            })
+            .collect();
+
+        // calibrated on the coldest replica, the others are hotter by the
+        // ladder anyway
+        if let Some(acceptance) = annealing.auto_temperature {
+            let temperature = replicas[0]
+                .calibrate(&target, acceptance)
+                .unwrap_or(annealing.temperature);
+            for replica in &mut replicas {
+                replica.start_at(annealing, temperature);
+            }
+        }
+
+        let mut tempering = Self {
+            rng: CountingRng::new(MersenneTwister64::new(annealing.seed)),
+            best_shapes: Vec::new(),
+            best_diff: f64::MAX,
+            generation: 0,
+            exchanges: (0, 0),
+            threads: threads(&config),
+            replicas,
+            config,
+            target,
+            observers: Vec::new(),
+        };
+        tempering.set_ladder();
+        tempering.update_best();
+        Ok(tempering)
+    }
+
+    /// Continue the run saved in `checkpoint`, which must have been made
+    /// with the same target and config
+    pub fn resume(
+        target: Target,
+        config: TemperingConfig,
+        checkpoint: &Checkpoint,
+    ) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let saved = checkpoint.field("replicas")?.as_array().unwrap_or_default();
+        if saved.len() != config.replicas {
+            return Err(invalid(format!(
+                "checkpoint has {} replicas, expected {}",
+                saved.len(),
+                config.replicas
+            )));
+        }
+        // each replica is saved like a single annealing run
+        let replicas = saved
+            .iter()
+            .enumerate()
+            .map(|(i, state)| {
+                let replica = Checkpoint {
+                    args: Vec::new(),
+                    generation: checkpoint.generation,
+                    state: state.clone(),
+                };
+                let seed = replica_seed(&config.annealing, i);
+                Chain::resume(&target, &config.annealing, seed, &replica)
+            })
+            .collect::<io::Result<_>>()?;
+
+        let mut tempering = Self {
+            rng: CountingRng::resume(
+                MersenneTwister64::new(config.annealing.seed),
+                checkpoint.u64("rng_draws")?,
+            ),
+            best_shapes: checkpoint::shapes_from_json(
+                checkpoint.field("best_shapes")?,
+                target.reference.width,
+                target.reference.height,
+            )?,
+            best_diff: checkpoint.f64("best_diff")?,
+            generation: checkpoint.generation,
+            exchanges: (
+                checkpoint.u64("exchanges_tried")?,
+                checkpoint.u64("exchanges_made")?,
+            ),
+            threads: threads(&config),
+            replicas,
+            config,
+            target,
+            observers: Vec::new(),
+        };
+        tempering.set_ladder();
+        Ok(tempering)
+    }
+
+    pub fn config(&self) -> &TemperingConfig {
+        &self.config
+    }
+
+    /// Number of worker threads
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The temperature of each replica, coldest first
+    pub fn temperatures(&self) -> Vec<f64> {
+        self.replicas
+            .iter()
+            .map(|r| r.temperature * r.scale)
+            .collect()
+    }
+
+    /// The temperature the coldest replica started at, which differs from
+    /// `config().annealing.temperature` if it was calibrated
+    pub fn initial_temperature(&self) -> f64 {
+        self.replicas[0].cooling.initial
+    }
+
+    /// Fraction of the exchanges tried so far that were made
+    pub fn exchange_rate(&self) -> f64 {
+        let (tried, made) = self.exchanges;
+        made as f64 / tried.max(1) as f64
+    }
+
+    // All replicas follow the same schedule, each `ladder` times hotter
+    // than the one before
+    fn set_ladder(&mut self) {
+        for (i, replica) in self.replicas.iter_mut().enumerate() {
+            replica.scale = self.config.ladder.powi(i as i32);
+        }
+    }
+
+    // Returns whether the best shapes of any replica beat the best so far
+    fn update_best(&mut self) -> bool {
+        let Some(best) = self
+            .replicas
+            .iter()
+            .min_by(|a, b| a.best_diff.total_cmp(&b.best_diff))
+        else {
+            return false;
+        };
+        if best.best_diff < self.best_diff {
+            self.best_diff = best.best_diff;
+            self.best_shapes = best.best_shapes.clone();
+            return true;
+        }
+        false
+    }
+
+    // Offer every other pair of neighbours, alternating between the even
+    // and odd pairs, the chance to trade their shapes. The colder replica
+    // of a pair always takes the shapes of the hotter one if they are
+    // better, and otherwise with a probability that falls with how much
+    // worse they are, as for a mutation.
+    fn exchange(&mut self) {
+        let round = self.generation / self.config.exchange_interval;
+        for i in ((round % 2) as usize..self.replicas.len().saturating_sub(1)).step_by(2) {
+            let (cold, hot) = self.replicas.split_at_mut(i + 1);
+            let (cold, hot) = (&mut cold[i], &mut hot[0]);
+            let d = exchange_exponent(
+                cold.current_diff,
+                hot.current_diff,
+                cold.temperature * cold.scale,
+                hot.temperature * hot.scale,
+            );
+            let accept = d >= 0.0 || (self.rng.next_u64() as f64 / u64::MAX as f64) < d.exp();
+            self.exchanges.0 += 1;
+            if accept {
+                cold.exchange(hot);
+                self.exchanges.1 += 1;
+            }
+        }
+    }
+}
+
+// Logarithm of the probability that replicas at temperatures `t_cold` and
+// `t_hot` with errors `cold` and `hot` trade their shapes; they always do
+// if it is not negative. The errors are relative to the smaller one, as for
+// a mutation.
+fn exchange_exponent(cold: f64, hot: f64, t_cold: f64, t_hot: f64) -> f64 {
+    (cold - hot) / (cold.min(hot) + 1.0) * (1.0 / t_cold - 1.0 / t_hot)
+}
+
+// The random number generator of replica `i` is seeded from the run's seed
+fn replica_seed(config: &AnnealingConfig, i: usize) -> u64 {
+    stream_seed(config.seed, 0, i as u64)
+}
+
+// The replicas are shared out between the threads, so there is no point in
+// more threads than replicas
+fn threads(config: &TemperingConfig) -> usize {
+    let threads = match config.threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    threads.min(config.replicas)
+}
+
+impl<S: Shape> Optimizer<S> for ParallelTempering<S> {
+    /// Run `exchange_interval` generations of every replica, then the
+    /// exchanges. The result does not depend on the number of threads.
+    fn step(&mut self) -> io::Result<()> {
+        let (target, config) = (&self.target, &self.config.annealing);
+        let generations = self.generation..self.generation + self.config.exchange_interval;
+        let chunk_size = self.replicas.len().div_ceil(self.threads).max(1);
+        let added = std::thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .replicas
+                .chunks_mut(chunk_size)
+                .map(|chunk| {
+                    let generations = generations.clone();
+                    scope.spawn(move || {
+                        let mut added = false;
+                        for replica in chunk {
+                            for generation in generations.clone() {
+                                added |= replica.cool(target, config, generation);
+                                replica.mutate(target, config);
+                            }
+                        }
+                        added
+                    })
+                })
+                .collect();
+            let mut added = false;
+            for worker in workers {
+                added |= worker.join().expect("worker thread panicked");
+            }
+            added
+        });
+        self.generation = generations.end;
+        self.exchange();
+
+        if added {
+            observer::notify(self, |o, opt| o.on_shape_added(opt))?;
+        }
+        if self.update_best() {
+            observer::notify(self, |o, opt| o.on_improvement(opt))?;
+        }
+        observer::notify(self, |o, opt| o.on_generation(opt))
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn generations_per_step(&self) -> u64 {
+        self.config.exchange_interval
+    }
+
+    fn best(&self) -> &[S] {
+        &self.best_shapes
+    }
+
+    fn best_fitness(&self) -> f64 {
+        self.best_diff
+    }
+
+    /// The shapes of the coldest replica
+    fn current(&self) -> &[S] {
+        &self.replicas[0].shapes
+    }
+
+    fn fitness(&self) -> f64 {
+        self.replicas[0].current_diff
+    }
+
+    fn target(&self) -> &Target {
+        &self.target
+    }
+
+    fn status(&self) -> String {
+        let coldest = &self.replicas[0];
+        format!(
+            "coldest={:.4}, best={:.4}, temp={:.6}, exchanges={:.0}%, shapes={}",
+            self.target.normalise(coldest.current_diff),
+            self.target.normalise(self.best_diff),
+            coldest.temperature,
+            100.0 * self.exchange_rate(),
+            coldest.shapes.len()
+        )
+    }
+
+    fn snapshot(&self) -> Json {
+        Json::object(vec![
+            ("rng_draws", Json::number(self.rng.draws())),
+            ("exchanges_tried", Json::number(self.exchanges.0)),
+            ("exchanges_made", Json::number(self.exchanges.1)),
+            ("best_diff", Json::number(self.best_diff)),
+            ("best_shapes", checkpoint::shapes_to_json(&self.best_shapes)),
+            (
+                "replicas",
+                Json::Array(self.replicas.iter().map(Chain::to_json).collect()),
+            ),
+        ])
+    }
+
+    fn observers(&mut self) -> &mut Vec<Box<dyn Observer<S>>> {
+        &mut self.observers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrameBuffer, MetricKind, Primitive};
+
+    fn target() -> Target {
+        let mut reference = FrameBuffer::new(24, 16);
+        for (i, px) in reference.pixels.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % 24, i / 24);
+            px.copy_from_slice(&[(x * 10) as u8, (y * 15) as u8, 128]);
+        }
+        Target::new(reference, MetricKind::Euclidean)
+    }
+
+    fn config() -> TemperingConfig {
+        let annealing = AnnealingConfig::default()
+            .num_shapes(4)
+            .temperature(0.5)
+            .add_interval(5)
+            .seed(3);
+        TemperingConfig::default()
+            .annealing(annealing)
+            .replicas(4)
+            .ladder(3.0)
+            .exchange_interval(10)
+            .threads(2)
+    }
+
+    #[test]
+    fn better_shapes_always_sink_to_the_colder_replica() {
+        assert!(exchange_exponent(2.0, 1.0, 0.5, 1.0) > 0.0);
+        assert_eq!(exchange_exponent(1.0, 1.0, 0.5, 1.0), 0.0);
+        // replicas at the same temperature always trade
+        assert_eq!(exchange_exponent(1.0, 2.0, 1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn worse_shapes_sink_less_often_the_worse_they_are() {
+        let p = |hot: f64| exchange_exponent(1.0, hot, 0.5, 1.0).exp();
+        assert!((p(1.5) - (-0.25f64).exp()).abs() < 1e-12);
+        assert!(p(1.5) < 1.0);
+        assert!(p(3.0) < p(1.5));
+        assert!(p(100.0) < 1e-10);
+        // the hotter the hot replica, the less likely the trade
+        let q = |t_hot: f64| exchange_exponent(1.0, 1.5, 0.5, t_hot).exp();
+        assert!(q(4.0) < q(1.0));
+    }
+
+    #[test]
+    fn exchange_applies_the_metropolis_criterion() {
+        let mut tempering =
+            ParallelTempering::<Primitive>::new(target(), config().replicas(2)).unwrap();
+        let geometry = |t: &ParallelTempering<Primitive>, i: usize| -> Vec<Vec<f32>> {
+            t.replicas[i].shapes.iter().map(Shape::geometry).collect()
+        };
+        let (cold, hot) = (geometry(&tempering, 0), geometry(&tempering, 1));
+        assert_ne!(cold, hot);
+
+        // much worse shapes are as good as never taken
+        tempering.replicas[0].current_diff = 0.0;
+        tempering.replicas[1].current_diff = 1e9;
+        tempering.exchange();
+        assert_eq!(tempering.exchanges, (1, 0));
+        assert_eq!(geometry(&tempering, 0), cold);
+
+        // better ones always are, and the errors go with them
+        tempering.replicas[0].current_diff = 2e6;
+        tempering.replicas[1].current_diff = 1e6;
+        tempering.exchange();
+        assert_eq!(tempering.exchanges, (2, 1));
+        assert_eq!(geometry(&tempering, 0), hot);
+        assert_eq!(geometry(&tempering, 1), cold);
+        assert_eq!(tempering.replicas[0].current_diff, 1e6);
+        assert_eq!(tempering.replicas[1].current_diff, 2e6);
+
+        // on odd rounds only the odd pairs trade, and two replicas have none
+        tempering.generation = 10;
+        tempering.exchange();
+        assert_eq!(tempering.exchanges, (2, 1));
+    }
+
+    #[test]
+    fn replicas_are_ordered_by_temperature() {
+        let mut tempering = ParallelTempering::<Primitive>::new(target(), config()).unwrap();
+        let check = |tempering: &ParallelTempering<Primitive>| {
+            let temperatures = tempering.temperatures();
+            assert_eq!(temperatures.len(), 4);
+            for pair in temperatures.windows(2) {
+                assert!((pair[1] / pair[0] - 3.0).abs() < 1e-9, "{temperatures:?}");
+            }
+        };
+        check(&tempering);
+        assert_eq!(tempering.temperatures()[0], 0.5);
+        for _ in 0..10 {
+            tempering.step().unwrap();
+            check(&tempering);
+        }
+        assert_eq!(tempering.generation(), 100);
+        assert!(tempering.exchanges.0 > 0);
+    }
+}